@@ -1,207 +1,709 @@
-use crate::comm::{self, AGG_SAMPLE_SIZE, FFT_BUF_SIZE, PORT_BUF_SIZE};
-use crate::jackit;
+use crate::cli;
+use crate::comm;
+use crate::meters::{LoudnessHistory, Meters};
+use crate::osc::{self, Control};
 use crate::portbuf;
+use crate::scope::Scope;
+use crate::source::AudioSource;
+use crate::spectrogram::Spectrogram;
+use crate::timeseries::TimeSeries;
+use crate::vectorscope::Vectorscope;
+use crate::window::{Window, WindowGains};
 
-use egui::plot::{Line, Plot, PlotBounds, PlotPoints};
+use egui::plot::{GridInput, GridMark, Legend, Line, LineStyle, Plot, PlotPoints};
+use std::collections::HashMap;
 
 macro_rules! label {
     ( $ui:expr, $($arg:tt)* ) => {$ui.label(format!($($arg)*))};
 }
 
-pub struct TemplateApp<const N: usize> {
+/// Distinct trace colours, assigned by port_idx
+const PORT_COLORS: [egui::Color32; 8] = [
+    egui::Color32::from_rgb(0x4e, 0x9a, 0xf0),
+    egui::Color32::from_rgb(0xf0, 0x8c, 0x3c),
+    egui::Color32::from_rgb(0x5c, 0xc8, 0x5c),
+    egui::Color32::from_rgb(0xe0, 0x4f, 0x5f),
+    egui::Color32::from_rgb(0xb0, 0x7c, 0xe8),
+    egui::Color32::from_rgb(0xe8, 0xd0, 0x48),
+    egui::Color32::from_rgb(0x48, 0xd0, 0xd0),
+    egui::Color32::from_rgb(0xe8, 0x78, 0xc8),
+];
+
+pub fn port_color(port_idx: usize) -> egui::Color32 {
+    PORT_COLORS[port_idx % PORT_COLORS.len()]
+}
+
+/// Axis label for a frequency, switching to kHz above 1000 Hz
+pub fn format_hz(freq: f64) -> String {
+    if freq.abs() >= 1_000.0 {
+        format!("{} kHz", (freq / 100.0).round() / 10.0)
+    } else {
+        format!("{} Hz", freq.round())
+    }
+}
+
+pub struct TemplateApp {
     // sub-systems
-    jackit: jackit::JackIt,
-    portbufs: Vec<portbuf::PortBuf<N>>,
+    source: Box<dyn AudioSource>,
+    portbufs: Vec<portbuf::PortBuf>,
     bus: comm::Bus,
-    plots: Vec<Box<dyn XPlot<N>>>,
+    plots: Vec<Box<dyn XPlot>>,
+    /// Indices into `plots` of those shown, top to bottom
+    layout: Vec<usize>,
+    /// Every plot is holding what it last showed
+    frozen: bool,
+    meters: Meters,
+    args: cli::Args,
+    // port editor
+    new_port_name: String,
+    port_error: Option<String>,
 }
 
-impl<const N: usize> TemplateApp<N> {
-    pub fn new(bus: comm::Bus, jackit: jackit::JackIt, portbufs: Vec<portbuf::PortBuf<N>>) -> Self {
+impl TemplateApp {
+    pub fn new(
+        bus: comm::Bus,
+        source: Box<dyn AudioSource>,
+        portbufs: Vec<portbuf::PortBuf>,
+        args: cli::Args,
+    ) -> Self {
+        let sample_rate = source.sample_rate() as f64;
+        let fft = portbuf::FftConfig::new(args.fft_size);
+        let plots: Vec<Box<dyn XPlot>> = vec![
+            Box::new(Scope::new()),
+            Box::new(FreqScope::new(sample_rate, fft)),
+            Box::new(Spectrogram::new(sample_rate)),
+            Box::new(TimeSeries::new()),
+            Box::new(LoudnessHistory::new()),
+            Box::new(Vectorscope::new()),
+        ];
         TemplateApp {
-            jackit,
+            source,
             portbufs,
             bus,
-            plots: vec![],
+            layout: (0..plots.len()).collect(),
+            plots,
+            frozen: false,
+            meters: Meters::new(),
+            args,
+            new_port_name: String::new(),
+            port_error: None,
+        }
+    }
+
+    /// Register a port on the source and spawn the PortBuf consuming it
+    fn add_port(&mut self, name: &str) -> anyhow::Result<()> {
+        // new ports pick up whatever fft the running ports have been switched to
+        let fft = match self.portbufs.first() {
+            Some(pb) => pb.fft_info().0,
+            None => portbuf::FftConfig::new(self.args.fft_size),
+        };
+        let (full_name, rb) = self.source.add_port(name)?;
+        let port_idx = self
+            .portbufs
+            .iter()
+            .map(|pb| pb.port_idx + 1)
+            .max()
+            .unwrap_or(0);
+        let mut pb = portbuf::PortBuf::new(
+            port_idx,
+            full_name,
+            false,
+            self.source.sample_rate(),
+            self.args.history,
+        );
+        pb.activate(portbuf::PortBufProcessConfig {
+            rb,
+            agg_bin_size: self.args.agg_size,
+            trigger: crate::trigger::TriggerConfig::default(),
+            meter: self.meters.config,
+            fft,
+            bus: self.bus.clone(),
+        })?;
+        for plt in &mut self.plots {
+            plt.port_added(&pb);
+        }
+        self.portbufs.push(pb);
+        Ok(())
+    }
+
+    /// Quit the PortBuf consuming a port and unregister it from the source
+    fn remove_port(&mut self, port_name: &str) -> anyhow::Result<()> {
+        if let Some(idx) = self.portbufs.iter().position(|pb| pb.name == port_name) {
+            self.portbufs.remove(idx).quit();
+        }
+        self.source.remove_port(port_name)
+    }
+
+    /// Apply a control from the UI or an OSC client, or say why it can't be
+    fn apply(&mut self, control: &Control) -> Result<(), String> {
+        match control {
+            Control::Layout(keys) => {
+                let mut layout = Vec::new();
+                for key in keys {
+                    let idx = self
+                        .plots
+                        .iter()
+                        .position(|plt| osc::plot_key(plt.name()) == *key)
+                        .ok_or_else(|| {
+                            let keys: Vec<String> = self
+                                .plots
+                                .iter()
+                                .map(|plt| osc::plot_key(plt.name()))
+                                .collect();
+                            format!("no plot {key}, expects some of {}", keys.join(", "))
+                        })?;
+                    if !layout.contains(&idx) {
+                        layout.push(idx);
+                    }
+                }
+                self.layout = layout;
+            }
+            Control::PortEnabled(name, enabled) => {
+                // by its full name or the name it was registered with
+                let pb = self
+                    .portbufs
+                    .iter()
+                    .find(|pb| pb.name == *name || pb.name.rsplit(':').next() == Some(name))
+                    .ok_or_else(|| format!("no port {name}"))?;
+                // as if the source had (dis)connected it
+                self.bus.send(comm::Update::Source(comm::Source::Connected {
+                    connected: *enabled,
+                    port_names: vec![pb.name.clone()],
+                }));
+            }
+            Control::Freeze(frozen) => {
+                self.frozen = *frozen;
+                self.plots.iter_mut().for_each(|plt| {
+                    plt.control(control, &self.portbufs);
+                });
+            }
+            _ => {
+                let taken = self
+                    .plots
+                    .iter_mut()
+                    .map(|plt| plt.control(control, &self.portbufs))
+                    // every plot gets the control, so no short circuit
+                    .filter(|taken| *taken)
+                    .count()
+                    > 0;
+                if !taken {
+                    return Err("no plot takes it".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Freezing, and which plots are shown
+    fn layout_ui(&mut self, ui: &mut egui::Ui) {
+        let mut frozen = self.frozen;
+        ui.horizontal(|ui| {
+            ui.toggle_value(&mut frozen, "Freeze")
+                .on_hover_text("Hold every plot as it is");
+            ui.separator();
+            for (idx, plt) in self.plots.iter().enumerate() {
+                let mut shown = self.layout.contains(&idx);
+                if ui.checkbox(&mut shown, plt.name()).changed() {
+                    match shown {
+                        true => self.layout.push(idx),
+                        false => self.layout.retain(|i| *i != idx),
+                    }
+                }
+            }
+        });
+        if frozen != self.frozen {
+            // freezing every plot never fails
+            let _ = self.apply(&Control::Freeze(frozen));
+        }
+    }
+
+    fn ports_ui(&mut self, ui: &mut egui::Ui) {
+        let mut remove = None;
+        ui.horizontal_wrapped(|ui| {
+            for pb in &self.portbufs {
+                let text = egui::RichText::new(format!(
+                    "{} {}",
+                    if pb.enabled { "●" } else { "○" },
+                    pb.name
+                ))
+                .color(port_color(pb.port_idx));
+                ui.label(text);
+                // the port fell behind its source, or the source dropped samples
+                let (overruns, dropped) = (pb.overruns(), pb.dropped());
+                if overruns > 0 || dropped > 0 {
+                    ui.colored_label(
+                        ui.visuals().error_fg_color,
+                        format!("⚠ {dropped} dropped, {overruns} overrun"),
+                    )
+                    .on_hover_text(
+                        "Samples missing between the source's chunks, and samples \
+                         that didn't fit in the port's ring buffer",
+                    );
+                }
+                if self.source.supports_port_changes()
+                    && ui.small_button("✖").on_hover_text("Remove port").clicked()
+                {
+                    remove = Some(pb.name.clone());
+                }
+                ui.separator();
+            }
+
+            if self.source.supports_port_changes() {
+                let edit = ui.add(
+                    egui::TextEdit::singleline(&mut self.new_port_name)
+                        .hint_text("port name")
+                        .desired_width(80.0),
+                );
+                let submitted = edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if (ui.button("➕ Add port").clicked() || submitted)
+                    && !self.new_port_name.is_empty()
+                {
+                    let name = std::mem::take(&mut self.new_port_name);
+                    self.port_error = self.add_port(&name).err().map(|e| e.to_string());
+                }
+            }
+
+            if let Some(err) = &self.port_error {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
+        });
+
+        if let Some(port_name) = remove {
+            self.port_error = self.remove_port(&port_name).err().map(|e| e.to_string());
         }
     }
 }
 
-impl<const N: usize> eframe::App for TemplateApp<N> {
+impl eframe::App for TemplateApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let updates = &self.bus.updates(false);
-        self.jackit.update(updates);
+        self.source.update(updates);
         for pb in &mut self.portbufs {
             pb.update(updates);
         }
-
-        // plot controller
+        for plt in &mut self.plots {
+            plt.update(updates)
+        }
         for updt in updates {
-            match updt {
-                comm::Update::Jack(comm::Jack::Connected {
-                    connected,
-                    port_names,
-                }) => {
-                    if *connected {
-                        self.plots = vec![
-                            Box::new(Scope::new(port_names.clone())),
-                            Box::new(FreqScope::new(
-                                port_names.clone(),
-                                self.jackit.sample_rate() as f64,
-                            )),
-                        ]
-                    } else {
-                        self.plots = vec![];
-                    }
+            if let comm::Update::Control(request) = updt {
+                match self.apply(&request.control) {
+                    Ok(()) => request.ack(),
+                    Err(reason) => request.error(&reason),
                 }
-                _ => (),
             }
         }
-        // for plt in &mut self.plots {
-        //     plt.update(updates)
-        // }
+
+        egui::TopBottomPanel::top("Source").show(ctx, |ui| {
+            self.source.ui(ui);
+            self.ports_ui(ui);
+            self.layout_ui(ui);
+        });
+
+        egui::SidePanel::left("Meters").show(ctx, |ui| self.meters.ui(ui, &self.portbufs));
 
         if cfg!(debug_assertions) {
-            egui::SidePanel::right("Diagnostics")
-                .show(ctx, |ui| diagnostics(ui, &self.portbufs, &self.jackit));
+            egui::SidePanel::right("Diagnostics").show(ctx, |ui| {
+                diagnostics(ui, &self.portbufs, self.source.as_ref(), &self.args)
+            });
         }
 
         egui::CentralPanel::default().show(ctx, |ui| {
-	    let panel_rect = ui.available_rect_before_wrap();
-	    let plot_height = panel_rect.height() / self.plots.len() as f32 - 1.0;
-	    let plot_size = &[panel_rect.width(), plot_height];
-            for (i, plt) in self.plots.iter_mut().enumerate() {
-		ui.allocate_ui(plot_size.into(), |ui| {
+            let panel_rect = ui.available_rect_before_wrap();
+            let plot_height = panel_rect.height() / self.layout.len().max(1) as f32 - 1.0;
+            let plot_size = &[panel_rect.width(), plot_height];
+            for idx in &self.layout {
+                let plt = &mut self.plots[*idx];
+                ui.allocate_ui(plot_size.into(), |ui| {
                     plt.plot(ui, &self.portbufs);
-		});
+                });
             }
         });
     }
 
     fn on_exit(&mut self) {
         self.portbufs.iter_mut().for_each(|pb| pb.quit());
-        self.jackit.stop();
+        self.source.stop();
         std::thread::sleep(comm::PORT_BUF_WAIT_DUR);
     }
 }
 
-trait XPlot<const N: usize> {
-    fn plot(&mut self, ui: &mut egui::Ui, bufs: &Vec<portbuf::PortBuf<N>>);
-    fn update(&mut self, updts: &Vec<comm::Update>);
+/// Grid marks for a log10(Hz) axis: heavy lines on decades and lighter ones
+/// on the octave band centres (1 kHz * 2^n)
+pub fn log_freq_grid(input: GridInput) -> Vec<GridMark> {
+    let (lo, hi) = input.bounds;
+    let in_bounds = |x: &f64| (lo..=hi).contains(x);
+    let decades = (lo.floor() as i32..=hi.ceil() as i32)
+        .map(|k| k as f64)
+        .filter(in_bounds)
+        .map(|value| GridMark {
+            value,
+            step_size: 1.0,
+        });
+    let octave = 2f64.log10();
+    let octaves = (-6..=5)
+        .filter(|n| *n != 0)
+        .map(|n| 3.0 + n as f64 * octave)
+        .filter(in_bounds)
+        .map(|value| GridMark {
+            value,
+            step_size: octave,
+        });
+    decades.chain(octaves).collect()
 }
 
-struct Scope {
-    port_names: Vec<String>,
-    time_window: f64,
+/// Vertical scale of the FreqScope
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Scale {
+    Power,
+    Amplitude,
+    /// 10 log10 of the power, 0 dB being a full scale sinusoid
+    Dbfs,
 }
 
-impl Scope {
-    fn new(port_names: Vec<String>) -> Self {
-        Scope {
-            port_names,
-            time_window: 0.0025, // 400hz sine wave period
+impl Scale {
+    const ALL: [Scale; 3] = [Scale::Power, Scale::Amplitude, Scale::Dbfs];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Scale::Power => "Power",
+            Scale::Amplitude => "Amplitude",
+            Scale::Dbfs => "dBFS",
         }
     }
 }
 
-impl<const N: usize> XPlot<N> for Scope {
-    fn plot(&mut self, ui: &mut egui::Ui, portbufs: &Vec<portbuf::PortBuf<N>>) {
-        ui.add(egui::Slider::new(&mut self.time_window, 5.0e-4..=0.01).text("Time Window"));
-        let lines: Vec<Line> = self
-            .port_names
-            .iter()
-            .filter_map(|port_name| portbufs.iter().find(|pb| &pb.name == port_name))
-            .map(|pb| Line::new(PlotPoints::new(pb.time_window(self.time_window, 0.0))))
-            .collect();
-        Plot::new("Scope").show(ui, |plot_ui| {
-            lines.into_iter().for_each(|line| plot_ui.line(line));
-            plot_ui.set_plot_bounds(PlotBounds::from_min_max(
-                [0.0, -1.1],
-                [self.time_window, 1.1],
-            ))
-        });
-    }
-
-    fn update(&mut self, updts: &Vec<comm::Update>) {
-        for updt in updts {
-            match updt {
-                comm::Update::Jack(comm::Jack::Connected {
-                    connected: _,
-                    port_names,
-                }) => {
-                    self.port_names.push(
-                        port_names
-                            .first()
-                            .expect("Scope.update port name update to contain port name")
-                            .clone(),
-                    );
-                }
-                _ => (),
-            }
-        }
+/// A view of the PortBufs stacked in the central panel. Traces are drawn in
+/// their port's `port_color`
+pub trait XPlot {
+    /// Shown in the layout controls and matched by OSC layouts
+    fn name(&self) -> &'static str;
+    fn plot(&mut self, ui: &mut egui::Ui, bufs: &[portbuf::PortBuf]);
+    fn update(&mut self, _updts: &[comm::Update]) {}
+    /// Bring a port added at runtime in line with the plot's settings
+    fn port_added(&mut self, _pb: &portbuf::PortBuf) {}
+    /// Apply a control from the UI or an OSC client, returning false when
+    /// the plot has no part in it. Config the PortBufs hold is sent to them
+    /// here, so it's applied whether or not the plot is shown. Every plot
+    /// holds still while frozen.
+    fn control(&mut self, _control: &Control, _bufs: &[portbuf::PortBuf]) -> bool {
+        false
     }
 }
 
 struct FreqScope {
-    port_names: Vec<String>,
     sample_rate: f64,
+    fft: portbuf::FftConfig,
+    /// Show power spectral density rather than the power of a sinusoid in each bin
+    psd: bool,
+    log_freq: bool,
+    scale: Scale,
+    /// Lowest level drawn on the dBFS scale
+    db_floor: f64,
+    peak_hold: bool,
+    /// How fast the peak hold trace falls back to the spectrum
+    peak_decay_db_per_sec: f64,
+    /// Peak hold trace by port_idx, as (Hz, power) points
+    peaks: HashMap<usize, Vec<[f64; 2]>>,
+    /// Spectra by port_idx held while frozen, taken on the first repaint
+    /// after freezing
+    frozen: Option<HashMap<usize, portbuf::FreqWindow>>,
+    /// Last α and N set, kept while another averaging mode is selected
+    avg_alpha: f32,
+    avg_n: usize,
 }
 
 impl FreqScope {
-    fn new(port_names: Vec<String>, sample_rate: f64) -> Self {
+    fn new(sample_rate: f64, fft: portbuf::FftConfig) -> Self {
         FreqScope {
-            port_names,
             sample_rate,
+            fft,
+            psd: false,
+            log_freq: true,
+            scale: Scale::Dbfs,
+            db_floor: -120.0,
+            peak_hold: false,
+            peak_decay_db_per_sec: 20.0,
+            peaks: HashMap::new(),
+            frozen: None,
+            avg_alpha: 0.1,
+            avg_n: 8,
         }
     }
-}
-
-impl<const N: usize> XPlot<N> for FreqScope {
-    fn plot(&mut self, ui: &mut egui::Ui, portbufs: &Vec<portbuf::PortBuf<N>>) {
-        let lines: Vec<Line> = self
-            .port_names
-            .iter()
-            .filter_map(|port_name| portbufs.iter().find(|pb| &pb.name == port_name))
-            .map(|pb| Line::new(PlotPoints::new(pb.freq_window())))
-            .collect();
-        Plot::new("FreqScope").show(ui, |plot_ui| {
-            lines.into_iter().for_each(|line| plot_ui.line(line));
-            // plot_ui.set_plot_bounds(PlotBounds::from_min_max(
-            //     [0.0, 0.0],
-            //     [0.5 * self.sample_rate, 1.1],
-            // ))
-        });
-    }
 
-    fn update(&mut self, updts: &Vec<comm::Update>) {
-        for updt in updts {
-            match updt {
-                comm::Update::Jack(comm::Jack::Connected {
-                    connected: _,
-                    port_names,
-                }) => {
-                    self.port_names.push(
-                        port_names
-                            .first()
-                            .expect("Scope.update port name update to contain port name")
-                            .clone(),
+    /// FFT size, window and averaging selection, sent to the PortBufs as it
+    /// changes. Averaging alone is changed without replanning the FFT.
+    fn fft_ui(&mut self, ui: &mut egui::Ui, portbufs: &[portbuf::PortBuf]) {
+        let prev = self.fft;
+        let mut reset = false;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("FFT Size")
+                .selected_text(self.fft.size.to_string())
+                .show_ui(ui, |ui| {
+                    let mut size = comm::FFT_MIN_SIZE;
+                    while size <= comm::FFT_MAX_SIZE {
+                        ui.selectable_value(&mut self.fft.size, size, size.to_string());
+                        size *= 2;
+                    }
+                });
+            egui::ComboBox::from_label("Window")
+                .selected_text(self.fft.window.label())
+                .show_ui(ui, |ui| {
+                    for window in Window::ALL {
+                        // keep the current kaiser beta when reselecting kaiser
+                        let window = match (window, self.fft.window) {
+                            (Window::Kaiser { .. }, Window::Kaiser { beta }) => {
+                                Window::Kaiser { beta }
+                            }
+                            _ => window,
+                        };
+                        ui.selectable_value(&mut self.fft.window, window, window.label());
+                    }
+                });
+            egui::ComboBox::from_label("Overlap")
+                .selected_text(self.fft.overlap.label())
+                .show_ui(ui, |ui| {
+                    for overlap in portbuf::Overlap::ALL {
+                        ui.selectable_value(&mut self.fft.overlap, overlap, overlap.label());
+                    }
+                });
+            if let Window::Kaiser { beta } = &mut self.fft.window {
+                ui.add(egui::Slider::new(beta, crate::window::KAISER_BETA).text("β"));
+            }
+            egui::ComboBox::from_label("Averaging")
+                .selected_text(self.fft.averaging.label())
+                .show_ui(ui, |ui| {
+                    for averaging in portbuf::Averaging::ALL {
+                        // keep the last α or N set when reselecting their mode
+                        let averaging = match averaging {
+                            portbuf::Averaging::Exponential { .. } => {
+                                portbuf::Averaging::Exponential {
+                                    alpha: self.avg_alpha,
+                                }
+                            }
+                            portbuf::Averaging::Linear { .. } => {
+                                portbuf::Averaging::Linear { n: self.avg_n }
+                            }
+                            _ => averaging,
+                        };
+                        let label = averaging.label();
+                        ui.selectable_value(&mut self.fft.averaging, averaging, label);
+                    }
+                });
+            match &mut self.fft.averaging {
+                portbuf::Averaging::Exponential { alpha } => {
+                    ui.add(
+                        egui::Slider::new(alpha, 0.001..=1.0)
+                            .logarithmic(true)
+                            .text("α"),
                     );
+                    self.avg_alpha = *alpha;
+                }
+                portbuf::Averaging::Linear { n } => {
+                    ui.add(egui::Slider::new(n, 2..=128).text("N"));
+                    self.avg_n = *n;
                 }
                 _ => (),
             }
+            if self.fft.averaging != portbuf::Averaging::None {
+                reset = ui.button("Reset").clicked();
+            }
+        });
+        let replan = portbuf::FftConfig {
+            averaging: prev.averaging,
+            ..self.fft
+        } != prev;
+        if replan {
+            portbufs.iter().for_each(|pb| pb.set_fft(self.fft));
+        } else if self.fft.averaging != prev.averaging {
+            portbufs
+                .iter()
+                .for_each(|pb| pb.set_averaging(self.fft.averaging));
+        }
+        if reset {
+            portbufs.iter().for_each(|pb| pb.reset_averaging());
         }
     }
+
+    fn display_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.log_freq, "Log Freq");
+            egui::ComboBox::from_label("Scale")
+                .selected_text(self.scale.label())
+                .show_ui(ui, |ui| {
+                    for scale in Scale::ALL {
+                        ui.selectable_value(&mut self.scale, scale, scale.label());
+                    }
+                });
+            if self.scale == Scale::Dbfs {
+                ui.add(
+                    egui::DragValue::new(&mut self.db_floor)
+                        .clamp_range(-240.0..=-20.0)
+                        .suffix(" dB"),
+                );
+                ui.label("Floor");
+            }
+            let psd_changed = ui.checkbox(&mut self.psd, "PSD").changed();
+            if ui.checkbox(&mut self.peak_hold, "Peak Hold").changed() || psd_changed {
+                self.peaks.clear();
+            }
+            if self.peak_hold {
+                ui.add(
+                    egui::DragValue::new(&mut self.peak_decay_db_per_sec)
+                        .clamp_range(0.0..=200.0)
+                        .suffix(" dB/s"),
+                );
+                ui.label("Decay");
+            }
+        });
+    }
+
+    /// Raise the peak hold trace of a port to `points`, letting it decay
+    /// towards them by the time elapsed since the last repaint
+    fn hold_peaks(&mut self, port_idx: usize, points: &[[f64; 2]], dt: f64) -> Vec<[f64; 2]> {
+        let decay = 10f64.powf(-self.peak_decay_db_per_sec * dt / 10.0);
+        let peaks = self.peaks.entry(port_idx).or_default();
+        if peaks.len() != points.len() {
+            *peaks = points.to_vec();
+        }
+        for (peak, [_, p]) in peaks.iter_mut().zip(points) {
+            peak[1] = (peak[1] * decay).max(*p);
+        }
+        peaks.clone()
+    }
+
+    /// Map (Hz, power) points onto the selected axes
+    fn to_axes(&self, points: &mut Vec<[f64; 2]>) {
+        if self.log_freq {
+            // DC has no place on a log axis
+            points.retain(|[f, _]| *f > 0.0);
+        }
+        for [f, p] in points.iter_mut() {
+            if self.log_freq {
+                *f = f.log10();
+            }
+            *p = match self.scale {
+                Scale::Power => *p,
+                Scale::Amplitude => p.sqrt(),
+                Scale::Dbfs => (10.0 * p.log10()).max(self.db_floor),
+            };
+        }
+    }
+}
+
+impl XPlot for FreqScope {
+    fn name(&self) -> &'static str {
+        "Spectrum"
+    }
+
+    fn control(&mut self, control: &Control, portbufs: &[portbuf::PortBuf]) -> bool {
+        match control {
+            Control::FftSize(size) => self.fft.size = *size,
+            Control::FftWindow(window) => self.fft.window = *window,
+            Control::Freeze(true) => {
+                self.frozen.get_or_insert_with(HashMap::new);
+                return true;
+            }
+            Control::Freeze(false) => {
+                self.frozen = None;
+                return true;
+            }
+            _ => return false,
+        }
+        portbufs.iter().for_each(|pb| pb.set_fft(self.fft));
+        true
+    }
+
+    fn plot(&mut self, ui: &mut egui::Ui, portbufs: &[portbuf::PortBuf]) {
+        self.fft_ui(ui, portbufs);
+        self.display_ui(ui);
+        let dt = match self.frozen {
+            Some(_) => 0.0,
+            None => ui.input(|i| i.stable_dt) as f64,
+        };
+        let mut lines = Vec::new();
+        for pb in portbufs.iter().filter(|pb| pb.enabled) {
+            let portbuf::FreqWindow {
+                mut points,
+                bin_size,
+                gains,
+            } = match &mut self.frozen {
+                Some(frozen) => frozen
+                    .entry(pb.port_idx)
+                    .or_insert_with(|| pb.freq_window())
+                    .clone(),
+                None => pb.freq_window(),
+            };
+            if self.psd {
+                // power is calibrated to a sinusoid's peak amplitude squared, so halve
+                // it for mean square before spreading it over the window's noise bandwidth
+                let scale = 1.0 / (2.0 * gains.enbw as f64 * bin_size);
+                points.iter_mut().for_each(|[_, p]| *p *= scale);
+            }
+            let color = port_color(pb.port_idx);
+            if self.peak_hold {
+                let mut peaks = self.hold_peaks(pb.port_idx, &points, dt);
+                self.to_axes(&mut peaks);
+                lines.push(
+                    Line::new(PlotPoints::new(peaks))
+                        .color(color.gamma_multiply(0.6))
+                        .style(LineStyle::dashed_dense())
+                        .name(format!("{} peak", pb.name)),
+                );
+            }
+            self.to_axes(&mut points);
+            lines.push(
+                Line::new(PlotPoints::new(points))
+                    .color(color)
+                    .name(&pb.name),
+            );
+        }
+        let log_freq = self.log_freq;
+        let nyquist = 0.5 * self.sample_rate;
+        let to_hz = move |x: f64| if log_freq { 10f64.powf(x) } else { x };
+        let y_unit = match (self.scale, self.psd) {
+            (Scale::Dbfs, false) => " dBFS",
+            (Scale::Dbfs, true) => " dBFS/Hz",
+            _ => "",
+        };
+        // a separate plot per axis mode so each keeps its own zoom
+        let mut plot = Plot::new(("FreqScope", self.log_freq, self.scale))
+            .legend(Legend::default())
+            .x_axis_formatter(move |x, _| format_hz(to_hz(x)))
+            .label_formatter(move |name, p| {
+                format!("{name}\n{}\n{:.4}{y_unit}", format_hz(to_hz(p.x)), p.y)
+            });
+        plot = if self.log_freq {
+            plot.x_grid_spacer(log_freq_grid)
+                .include_x(1.0)
+                .include_x(nyquist.log10())
+        } else {
+            plot.include_x(0.0).include_x(nyquist)
+        };
+        if self.scale == Scale::Dbfs {
+            plot = plot.include_y(self.db_floor).include_y(0.0);
+        }
+        plot.show(ui, |plot_ui| {
+            lines.into_iter().for_each(|line| plot_ui.line(line));
+        });
+    }
 }
 
-pub fn diagnostics<const N: usize>(
+pub fn diagnostics(
     ui: &mut egui::Ui,
-    portbufs: &Vec<portbuf::PortBuf<N>>,
-    jackit: &jackit::JackIt,
+    portbufs: &[portbuf::PortBuf],
+    source: &dyn AudioSource,
+    args: &cli::Args,
 ) {
     ui.heading("Port Connections");
-    for portbuf::PortBuf { name, enabled, .. } in portbufs {
+    for portbuf::PortBuf {
+        name,
+        enabled,
+        port_idx,
+        ..
+    } in portbufs
+    {
         ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
-            ui.label(name);
+            ui.colored_label(port_color(*port_idx), name);
             if *enabled {
                 ui.label("☑");
             } else {
@@ -212,36 +714,51 @@ pub fn diagnostics<const N: usize>(
 
     ui.separator();
     ui.heading("Runtime Configuration");
-    let sample_rate = jackit.sample_rate() as f64;
+    let sample_rate = source.sample_rate() as f64;
     let sample_time = 1.0 / sample_rate;
-    let raw_buf_time_len = PORT_BUF_SIZE as f64 * sample_time;
-    let agg_buf_time_len = (AGG_SAMPLE_SIZE * PORT_BUF_SIZE) as f64 * sample_time;
-    let fft_bin_size = sample_rate / FFT_BUF_SIZE as f64;
+    let raw_buf_time_len = args.history as f64 * sample_time;
+    let (fft, gains) = match portbufs.first() {
+        Some(pb) => pb.fft_info(),
+        None => {
+            let fft = portbuf::FftConfig::new(args.fft_size);
+            (fft, WindowGains::new(&fft.window.coefficients(fft.size)))
+        }
+    };
+    let fft_bin_size = sample_rate / fft.size as f64;
     let fft_hi_freq = sample_rate / 2.0 - fft_bin_size;
+    label!(ui, "client name = {}", args.client_name);
     label!(ui, "sample rate = {sample_rate}");
     label!(ui, "sample time = {sample_time}");
     label!(ui, "raw buffer time length = {raw_buf_time_len}");
-    label!(ui, "agg buffer time length = {agg_buf_time_len}");
+    let mut bin_size = args.agg_size;
+    for level in 0..comm::SERIES_LEVELS {
+        let time_len = (bin_size * args.history) as f64 * sample_time;
+        label!(ui, "series level {level} time length = {time_len}");
+        bin_size *= comm::SERIES_LEVEL_FACTOR;
+    }
     label!(ui, "fft bin size = {fft_bin_size}");
     label!(ui, "fft bandwidth range = [0, {fft_hi_freq}]");
-
-    ui.separator();
-    ui.heading("Jack Process Diagnostics");
+    label!(ui, "fft window = {}", fft.window.label());
     label!(
         ui,
-        "Avg Process Time: {:?}",
-        jackit.timing.avg_diag_cycle_time
-    );
-    label!(
-        ui,
-        "Max Process Time: {:?}",
-        jackit.timing.max_diag_cycle_time
+        "fft hop = {} ({} overlap)",
+        fft.overlap.hop(fft.size),
+        fft.overlap.label()
     );
+    label!(ui, "window coherent gain = {}", gains.coherent_gain);
+    label!(ui, "window enbw = {} bins", gains.enbw);
+
+    ui.separator();
+    ui.heading(format!("{} Process Diagnostics", source.name()));
+    let timing = source.timing();
+    label!(ui, "Avg Process Time: {:?}", timing.avg_diag_cycle_time);
+    label!(ui, "Max Process Time: {:?}", timing.max_diag_cycle_time);
 
     ui.separator();
     ui.heading("PortBuf Process Diagnostics");
-    for portbuf::PortBuf { name, timing, .. } in portbufs {
-        ui.label(name);
+    for portbuf in portbufs {
+        let timing = portbuf.timing;
+        ui.label(&portbuf.name);
         label!(ui, "Avg Process Time: {:?}", timing.avg_diag_cycle_time);
         label!(ui, "Max Process Time: {:?}", timing.max_diag_cycle_time);
     }
@@ -250,23 +767,23 @@ pub fn diagnostics<const N: usize>(
     ui.heading("PortBuf Capacity");
     for portbuf in portbufs {
         ui.label(&portbuf.name);
-        let (agg, raw, fft) = portbuf.curr_idx();
+        let (series, raw, fft) = portbuf.curr_idx();
         ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
-            ui.label("agg: ");
+            ui.label("series: ");
             ui.add(egui::widgets::ProgressBar::new(
-                agg as f32 / comm::PORT_BUF_SIZE as f32,
+                series as f32 / portbuf.history as f32,
             ));
         });
         ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
             ui.label("raw: ");
             ui.add(egui::widgets::ProgressBar::new(
-                raw as f32 / comm::PORT_BUF_SIZE as f32,
+                raw as f32 / portbuf.history as f32,
             ));
         });
         ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
             ui.label("fft: ");
             ui.add(egui::widgets::ProgressBar::new(
-                fft as f32 / comm::PORT_BUF_SIZE as f32,
+                fft as f32 / portbuf::fft_history_len(portbuf.fft_info().0.size) as f32,
             ));
         });
     }
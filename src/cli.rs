@@ -0,0 +1,139 @@
+use crate::comm;
+use crate::generator::{Generator, Signal};
+use crate::jackit::JackIt;
+use crate::playback::Playback;
+use crate::source::AudioSource;
+use anyhow::{bail, Result};
+use clap::{Parser, ValueEnum};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    /// Listen to a running jack server
+    Jack,
+    /// Play back a WAV or FLAC file given with --file
+    File,
+    /// Synthesise test signals, one channel per --port
+    Gen,
+}
+
+/// SuperCollider visualiser. Plots scope and spectrum views of audio ports.
+#[derive(Debug, Clone, Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// Jack client name. Give each running instance its own
+    #[arg(short = 'n', long, default_value = "scviz")]
+    pub client_name: String,
+
+    /// Input port to register, repeat for more ports
+    #[arg(short, long = "port", default_value = "in_1")]
+    pub ports: Vec<String>,
+
+    /// Port to connect to each input port, in port order, e.g. system:capture_1
+    #[arg(short, long = "connect")]
+    pub connect: Vec<String>,
+
+    /// Audio source backend
+    #[arg(short, long, value_enum, default_value_t = Backend::Jack)]
+    pub source: Backend,
+
+    /// File to play back with `--source file`
+    #[arg(short, long)]
+    pub file: Option<PathBuf>,
+
+    /// Sample rate of the generator with `--source gen`
+    #[arg(long, default_value_t = 48_000)]
+    pub gen_sample_rate: usize,
+
+    /// Number of samples per FFT, a power of two
+    #[arg(long, default_value_t = comm::FFT_BUF_SIZE)]
+    pub fft_size: usize,
+
+    /// Number of raw samples, and bins of each time series level, each port keeps
+    #[arg(long, default_value_t = comm::PORT_BUF_SIZE)]
+    pub history: usize,
+
+    /// Number of samples aggregated into each time series bin
+    #[arg(long, default_value_t = comm::AGG_SAMPLE_SIZE)]
+    pub agg_size: usize,
+
+    /// Number of source process cycles each ring buffer can hold
+    #[arg(long, default_value_t = comm::RINGBUF_CYCLE_SIZE)]
+    pub ringbuf_cycles: usize,
+
+    /// What to drop when a port falls behind and its ring buffer fills
+    #[arg(long, value_enum, default_value_t = comm::OVERFLOW)]
+    pub overflow: comm::Overflow,
+
+    /// Run without a window, printing port diagnostics every second
+    #[arg(long)]
+    pub headless: bool,
+
+    /// UDP port to listen for OSC control messages on, e.g. from sclang.
+    /// No OSC server is run without it
+    #[arg(long)]
+    pub osc_port: Option<u16>,
+
+    /// Address the OSC server binds to. Give 0.0.0.0 to take messages from
+    /// other machines
+    #[arg(long, default_value = "127.0.0.1")]
+    pub osc_host: IpAddr,
+}
+
+impl Args {
+    pub fn validate(&self) -> Result<()> {
+        if !self.fft_size.is_power_of_two()
+            || !(comm::FFT_MIN_SIZE..=comm::FFT_MAX_SIZE).contains(&self.fft_size)
+        {
+            bail!(
+                "--fft-size {} must be a power of two in [{}, {}]",
+                self.fft_size,
+                comm::FFT_MIN_SIZE,
+                comm::FFT_MAX_SIZE
+            );
+        }
+        if self.agg_size == 0 || self.agg_size > comm::PORT_BUF_MAX_READ {
+            bail!(
+                "--agg-size {} must be in [1, {}]",
+                self.agg_size,
+                comm::PORT_BUF_MAX_READ
+            );
+        }
+        if self.history < self.agg_size {
+            bail!(
+                "--history {} must hold at least --agg-size {} samples",
+                self.history,
+                self.agg_size
+            );
+        }
+        if self.ports.len() > comm::MAX_PORTS {
+            bail!("at most {} ports are supported", comm::MAX_PORTS);
+        }
+        if self.ringbuf_cycles == 0 {
+            bail!("--ringbuf-cycles must be at least 1");
+        }
+        if self.headless && self.osc_port.is_some() {
+            bail!("--osc-port controls the window, so can't be used with --headless");
+        }
+        Ok(())
+    }
+
+    pub fn source(&self) -> Result<Box<dyn AudioSource>> {
+        Ok(match self.source {
+            Backend::Jack => Box::new(JackIt::new(
+                &self.client_name,
+                self.ports.clone(),
+                self.connect.clone(),
+            )?),
+            Backend::File => match &self.file {
+                Some(path) => Box::new(Playback::open(path)?),
+                None => bail!("--source file requires --file"),
+            },
+            Backend::Gen => Box::new(Generator::new(
+                self.gen_sample_rate,
+                vec![Signal::default(); self.ports.len()],
+            )),
+        })
+    }
+}
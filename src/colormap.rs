@@ -0,0 +1,85 @@
+use egui::Color32;
+
+/// Colour maps for intensity images such as the spectrogram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMap {
+    Viridis,
+    Magma,
+    Inferno,
+    Grayscale,
+}
+
+// matplotlib's maps sampled at nine evenly spaced points
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+const MAGMA: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [28, 16, 68],
+    [79, 18, 123],
+    [129, 37, 129],
+    [181, 54, 122],
+    [229, 80, 100],
+    [251, 135, 97],
+    [254, 194, 135],
+    [252, 253, 191],
+];
+
+const INFERNO: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [31, 12, 72],
+    [85, 15, 109],
+    [136, 34, 106],
+    [186, 54, 85],
+    [227, 89, 51],
+    [249, 140, 10],
+    [249, 201, 50],
+    [252, 255, 164],
+];
+
+const GRAYSCALE: [[u8; 3]; 2] = [[0, 0, 0], [255, 255, 255]];
+
+impl ColorMap {
+    pub const ALL: [ColorMap; 4] = [
+        ColorMap::Viridis,
+        ColorMap::Magma,
+        ColorMap::Inferno,
+        ColorMap::Grayscale,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ColorMap::Viridis => "Viridis",
+            ColorMap::Magma => "Magma",
+            ColorMap::Inferno => "Inferno",
+            ColorMap::Grayscale => "Grayscale",
+        }
+    }
+
+    /// Colour of `t` in [0, 1], clamping values outside of it
+    pub fn color(&self, t: f32) -> Color32 {
+        let anchors: &[[u8; 3]] = match self {
+            ColorMap::Viridis => &VIRIDIS,
+            ColorMap::Magma => &MAGMA,
+            ColorMap::Inferno => &INFERNO,
+            ColorMap::Grayscale => &GRAYSCALE,
+        };
+        let x = t.clamp(0.0, 1.0) * (anchors.len() - 1) as f32;
+        let i = (x as usize).min(anchors.len() - 2);
+        let frac = x - i as f32;
+        let lerp = |c: usize| {
+            let (a, b) = (anchors[i][c] as f32, anchors[i + 1][c] as f32);
+            (a + (b - a) * frac).round() as u8
+        };
+        Color32::from_rgb(lerp(0), lerp(1), lerp(2))
+    }
+}
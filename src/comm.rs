@@ -1,59 +1,103 @@
-use std::sync::Arc;
+use std::ops::Range;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 
 pub type RingProducer = ringbuf::producer::Producer<f32, Arc<ringbuf::HeapRb<f32>>>;
 pub type RingConsumer = ringbuf::consumer::Consumer<f32, Arc<ringbuf::HeapRb<f32>>>;
 
-/// How many Jack process cycles can fit into the ringbuf
+/// Default for how many Jack process cycles can fit into the ringbuf
 pub const RINGBUF_CYCLE_SIZE: usize = 10;
 
-/// How many point pairs can the underlying port buffers hold before overwriting
+/// Default for what a source does with samples that don't fit the ringbuf
+pub const OVERFLOW: Overflow = Overflow::DropOldest;
+
+/// Upper bound on the number of ports a source can run. Lets the jack process
+/// callback preallocate room for ports added at runtime.
+pub const MAX_PORTS: usize = 32;
+
+/// Default for how many point pairs the underlying port buffers hold before overwriting
 pub const PORT_BUF_SIZE: usize = 65_536;
 
-/// How many samples to aggregate over for the time series buffer. For efficiency
-/// chosen to match the typical jack_buffer_size.
+/// Default for how many samples to aggregate over for the time series buffer. For
+/// efficiency chosen to match the typical jack_buffer_size.
 pub const AGG_SAMPLE_SIZE: usize = 1024;
 
+/// Number of bins of each time series level summarised by one bin of the next
+pub const SERIES_LEVEL_FACTOR: usize = 8;
+
+/// Number of levels in each port's time series pyramid. At the defaults the
+/// coarsest bins summarise 8^3 * 1024 samples, so it holds days of history.
+pub const SERIES_LEVELS: usize = 4;
+
+/// How many samples software sources (file playback, generator) hand out per
+/// cycle. Matches AGG_SAMPLE_SIZE so every cycle delivers a full aggregation bin.
+pub const SOURCE_BUF_SIZE: usize = 1024;
+
 /// How long should the port buff wait between ring buffer reads
 pub const PORT_BUF_WAIT_DUR: std::time::Duration = std::time::Duration::from_millis(1);
 
-/// The number of FFT Samples to aggregate over. Divisible by 1024 and a power of 2
-/// for greatest efficiency
+/// Most samples a port buf pulls off its ring buffer per read
+pub const PORT_BUF_MAX_READ: usize = 8192;
+
+/// Default number of FFT Samples to aggregate over. A power of 2 for greatest
+/// efficiency
 pub const FFT_BUF_SIZE: usize = 8192;
 
-/// The size of the main channel bus
-pub const CHANNEL_BUS_SIZE: usize = 10;
+/// Smallest FFT size selectable at runtime
+pub const FFT_MIN_SIZE: usize = 256;
+
+/// Largest FFT size selectable at runtime
+pub const FFT_MAX_SIZE: usize = 65_536;
+
+/// Number of spectrum values each port keeps, so consumers of successive
+/// frames (e.g. the spectrogram) can catch up between repaints
+pub const FFT_HISTORY_SIZE: usize = 1 << 18;
+
+/// Fewest whole spectra each port keeps, whatever the FFT size
+pub const FFT_HISTORY_MIN_FRAMES: usize = 16;
+
+/// Number of recent trigger positions each port keeps, so the scope can find
+/// one with enough samples after it
+pub const TRIGGER_HISTORY: usize = 64;
+
+/// Number of recent gaps in a source's samples each port keeps
+pub const GAP_HISTORY: usize = 64;
+
+/// Number of loudness readings, one every 100ms block, each port keeps for
+/// the loudness history. An hour's worth.
+pub const LOUDNESS_HISTORY: usize = 36_000;
+
+/// The size of the main channel bus. Room for a burst of OSC controls
+/// arriving between repaints.
+pub const CHANNEL_BUS_SIZE: usize = 64;
 
 /// Number of Process Cycles to include in diagnostic aggregation
 pub const TIMING_DIAGNOSTIC_CYCLES: u32 = 10;
 
 // pub const AUDIO_BUFF_SIZE: usize = 8192;
-// pub const FFT_MAX_SIZE: usize = 8192;
 // pub const FFT_MAX_BUFF_SIZE: usize = 4097;
 // pub const MAX_DATA_LENGTH: usize = 10000;
 // pub const APP_WIDTH: f32 = 1200.0;
 // pub const APP_HEIGHT: f32 = 800.0;
 
+/// Events sent over the Bus. Periodic telemetry such as timing and overruns
+/// is shared through atomics instead, so that nothing publishing it can
+/// block on the Bus, or fill it up while the UI isn't draining it.
 #[derive(Debug)]
 pub enum Update {
-    Jack(Jack),
-    PortBuf(PortBuf),
+    Source(Source),
+    /// A control from an OSC client, to apply and answer
+    Control(crate::osc::Request),
 }
 
 #[derive(Debug)]
-pub enum Jack {
+pub enum Source {
     Connected {
         connected: bool,
         port_names: Vec<String>,
     },
-    TimingDiagnostics(TimingDiagnostics),
-}
-
-#[derive(Debug)]
-pub enum PortBuf {
-    TimingDiagnostics {
-        port_idx: usize,
-        timing: TimingDiagnostics,
-    },
 }
 
 #[derive(Clone)]
@@ -69,9 +113,19 @@ impl Bus {
         Bus { ctx, tx, rx }
     }
 
+    /// Send without blocking. The update is dropped if the Bus is full,
+    /// which only happens if the UI has stopped draining it.
     pub fn send(&self, updt: Update) {
-        self.tx.send(updt).expect("Bus.send to succeed");
+        if let Err(updt) = self.try_send(updt) {
+            eprintln!("Error: Bus dropped {:?}", updt);
+        }
+    }
+
+    /// Send without blocking, handing the update back if the Bus is full
+    pub fn try_send(&self, updt: Update) -> Result<(), Update> {
+        let sent = self.tx.try_send(updt).map_err(|e| e.into_inner());
         self.ctx.request_repaint();
+        sent
     }
 
     pub fn updates(&self, debug: bool) -> Vec<Update> {
@@ -89,6 +143,268 @@ impl Bus {
     }
 }
 
+/// Source frame time of the first of `len` samples pushed together to a port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkTag {
+    pub frame: u64,
+    pub len: usize,
+    /// First chunk pushed since samples were dropped for want of room
+    pub overrun: bool,
+}
+
+pub type TagProducer = ringbuf::producer::Producer<ChunkTag, Arc<ringbuf::HeapRb<ChunkTag>>>;
+pub type TagConsumer = ringbuf::consumer::Consumer<ChunkTag, Arc<ringbuf::HeapRb<ChunkTag>>>;
+
+/// What a source does with a cycle that doesn't fit in a port's ring buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Overflow {
+    /// Keep the newest samples of the cycle that overran, and have the
+    /// consumer skip the backlog ahead of them to catch back up with the
+    /// source
+    DropOldest,
+    /// Keep the backlog, pushing only what fits of the cycle
+    DropNewest,
+}
+
+/// Source side of a port: samples, plus a tag per pushed chunk placing them
+/// on the source's frame clock
+pub struct PortProducer {
+    pub samples: RingProducer,
+    pub tags: TagProducer,
+    overflow: Overflow,
+    /// Samples that didn't fit, shared with the consumer
+    overruns: Arc<AtomicU64>,
+    /// Samples were dropped since the last tag was pushed
+    overran: bool,
+}
+
+impl PortProducer {
+    /// Push `xs` as the samples starting at source frame time `frame`,
+    /// returning how many fit. What doesn't fit is dropped according to the
+    /// Overflow policy and counted as overrun. Nothing is pushed without room
+    /// for the tag, so every sample in the ring buffer can be placed in time.
+    pub fn push(&mut self, frame: u64, xs: &[f32]) -> usize {
+        let room = if self.tags.is_full() {
+            0
+        } else {
+            xs.len().min(self.samples.free_len())
+        };
+        if room < xs.len() {
+            self.overruns
+                .fetch_add((xs.len() - room) as u64, Ordering::Relaxed);
+            self.overran = true;
+        }
+        if room == 0 {
+            return 0;
+        }
+        let skip = match self.overflow {
+            Overflow::DropOldest => xs.len() - room,
+            Overflow::DropNewest => 0,
+        };
+        // the tag goes first so a consumer never sees samples without it.
+        // Only we push, so there is still room for the tag and samples.
+        let _ = self.tags.push(ChunkTag {
+            frame: frame + skip as u64,
+            len: room,
+            overrun: std::mem::take(&mut self.overran),
+        });
+        self.samples.push_slice(&xs[skip..skip + room])
+    }
+}
+
+/// PortBuf side of a port
+pub struct PortConsumer {
+    samples: RingConsumer,
+    tags: TagConsumer,
+    overflow: Overflow,
+    overruns: Arc<AtomicU64>,
+    /// Producer overruns as of the last catch_up
+    seen_overruns: u64,
+    /// Rest of the chunk the last pop stopped part way through
+    chunk: Option<ChunkTag>,
+    /// Frame time following the last sample popped
+    next_frame: u64,
+}
+
+impl PortConsumer {
+    /// Number of samples waiting
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// The producer's count of samples dropped for want of room, readable
+    /// from any thread
+    pub fn overrun_counter(&self) -> Arc<AtomicU64> {
+        self.overruns.clone()
+    }
+
+    /// Fill `out` with the oldest waiting samples, returning how many were
+    /// popped and adding (frame time, range of `out`) to `chunks` for each
+    /// run of them the source pushed together.
+    pub fn pop(&mut self, out: &mut [f32], chunks: &mut Vec<(u64, Range<usize>)>) -> usize {
+        let n = self.samples.pop_slice(out);
+        self.take_tags(n, |frame, range| chunks.push((frame, range)));
+        n
+    }
+
+    /// With the DropOldest policy, skip the backlog ahead of the newest chunk
+    /// pushed since the producer overran, returning how many samples were
+    /// skipped. That chunk and any after it are kept, and show up after a
+    /// gap when next popped.
+    pub fn catch_up(&mut self) -> usize {
+        let overruns = self.overruns.load(Ordering::Relaxed);
+        if overruns == self.seen_overruns || self.overflow == Overflow::DropNewest {
+            self.seen_overruns = overruns;
+            return 0;
+        }
+        // the counter is bumped before the chunk is pushed, so wait for it
+        let Some(keep) = self
+            .tags
+            .iter()
+            .enumerate()
+            .filter(|(_, tag)| tag.overrun)
+            .map(|(i, _)| i)
+            .last()
+        else {
+            return 0;
+        };
+        self.seen_overruns = overruns;
+        let backlog = self
+            .tags
+            .iter()
+            .take(keep)
+            .map(|tag| tag.len)
+            .sum::<usize>()
+            + self.chunk.take().map_or(0, |tag| tag.len);
+        self.tags.skip(keep);
+        self.samples.skip(backlog)
+    }
+
+    /// Split the next `n` samples into the chunks they were pushed as. The
+    /// producer pushes a tag before its samples, so one is only missing if
+    /// the samples came from elsewhere, and they're then taken to follow on.
+    fn take_tags(&mut self, n: usize, mut chunk: impl FnMut(u64, Range<usize>)) {
+        let mut offset = 0;
+        while offset < n {
+            let tag = self
+                .chunk
+                .take()
+                .or_else(|| self.tags.pop())
+                .unwrap_or(ChunkTag {
+                    frame: self.next_frame,
+                    len: n - offset,
+                    overrun: false,
+                });
+            let len = tag.len.min(n - offset);
+            if len < tag.len {
+                self.chunk = Some(ChunkTag {
+                    frame: tag.frame + len as u64,
+                    len: tag.len - len,
+                    overrun: false,
+                });
+            }
+            chunk(tag.frame, offset..offset + len);
+            self.next_frame = tag.frame + len as u64;
+            offset += len;
+        }
+    }
+}
+
+/// Ring buffers for a port holding `cycles` cycles of `cycle_size` samples
+pub fn port_ring(
+    cycle_size: usize,
+    cycles: usize,
+    overflow: Overflow,
+) -> (PortProducer, PortConsumer) {
+    let (samples_prod, samples_cons) = ringbuf::HeapRb::<f32>::new(cycle_size * cycles).split();
+    // file playback splits a cycle in two at its loop point
+    let (tags_prod, tags_cons) = ringbuf::HeapRb::<ChunkTag>::new(2 * cycles + 2).split();
+    let overruns = Arc::new(AtomicU64::new(0));
+    (
+        PortProducer {
+            samples: samples_prod,
+            tags: tags_prod,
+            overflow,
+            overruns: overruns.clone(),
+            overran: false,
+        },
+        PortConsumer {
+            samples: samples_cons,
+            tags: tags_cons,
+            overflow,
+            overruns,
+            seen_overruns: 0,
+            chunk: None,
+            next_frame: 0,
+        },
+    )
+}
+
+/// TimingDiagnostics shared out of a processing thread without ever blocking
+/// it. A seqlock: the one writer bumps the sequence number to odd while it
+/// stores, and readers retry any read that overlapped a store.
+#[derive(Debug, Clone, Default)]
+pub struct SharedTiming {
+    inner: Arc<TimingCell>,
+}
+
+#[derive(Debug, Default)]
+struct TimingCell {
+    seq: AtomicU64,
+    /// Nanoseconds
+    avg: AtomicU64,
+    max: AtomicU64,
+    min: AtomicU64,
+}
+
+impl SharedTiming {
+    pub fn new() -> SharedTiming {
+        SharedTiming::default()
+    }
+
+    /// Publish from the single thread recording `timing`. Lock and allocation
+    /// free, so safe to call from a realtime thread.
+    pub fn publish(&self, timing: &TimingDiagnostics) {
+        let nanos = |d: std::time::Duration| d.as_nanos().min(u64::MAX as u128) as u64;
+        let cell = &self.inner;
+        cell.seq.fetch_add(1, Ordering::Relaxed);
+        std::sync::atomic::fence(Ordering::Release);
+        cell.avg
+            .store(nanos(timing.avg_diag_cycle_time), Ordering::Relaxed);
+        cell.max
+            .store(nanos(timing.max_diag_cycle_time), Ordering::Relaxed);
+        cell.min
+            .store(nanos(timing.min_diag_cycle_time), Ordering::Relaxed);
+        cell.seq.fetch_add(1, Ordering::Release);
+    }
+
+    /// The last published timing, or None if nothing has been yet
+    pub fn load(&self) -> Option<TimingDiagnostics> {
+        let cell = &self.inner;
+        loop {
+            let seq = cell.seq.load(Ordering::Acquire);
+            if seq == 0 {
+                return None;
+            }
+            if seq % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let avg = cell.avg.load(Ordering::Relaxed);
+            let max = cell.max.load(Ordering::Relaxed);
+            let min = cell.min.load(Ordering::Relaxed);
+            std::sync::atomic::fence(Ordering::Acquire);
+            if cell.seq.load(Ordering::Relaxed) == seq {
+                let mut timing = TimingDiagnostics::new(0);
+                timing.avg_diag_cycle_time = std::time::Duration::from_nanos(avg);
+                timing.max_diag_cycle_time = std::time::Duration::from_nanos(max);
+                timing.min_diag_cycle_time = std::time::Duration::from_nanos(min);
+                return Some(timing);
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct TimingDiagnostics {
     diagnostic_proc_cycles: u32,
@@ -130,3 +446,72 @@ impl TimingDiagnostics {
         self.diagnostic_proc_cycle == self.diagnostic_proc_cycles
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop_all(cons: &mut PortConsumer) -> (Vec<f32>, Vec<(u64, Range<usize>)>) {
+        let mut out = vec![0.0; cons.len()];
+        let mut chunks = Vec::new();
+        cons.pop(&mut out, &mut chunks);
+        (out, chunks)
+    }
+
+    #[test]
+    fn port_ring_overflow() {
+        let (mut prod, mut cons) = port_ring(4, 1, Overflow::DropNewest);
+        assert!(prod.push(10, &[1.0, 2.0, 3.0]) == 3);
+        assert!(prod.push(13, &[4.0, 5.0, 6.0]) == 1);
+        assert!(cons.overrun_counter().load(Ordering::Relaxed) == 2);
+        // the backlog is kept
+        assert!(cons.catch_up() == 0);
+        let (out, chunks) = pop_all(&mut cons);
+        assert!(out == [1.0, 2.0, 3.0, 4.0]);
+        assert!(chunks == [(10, 0..3), (13, 3..4)]);
+
+        let (mut prod, mut cons) = port_ring(4, 1, Overflow::DropOldest);
+        prod.push(10, &[1.0, 2.0, 3.0]);
+        // the newest sample of the cycle is kept, tagged with its frame time
+        assert!(prod.push(13, &[4.0, 5.0, 6.0]) == 1);
+        assert!(cons.overrun_counter().load(Ordering::Relaxed) == 2);
+        // and the consumer drops the backlog ahead of it to catch up
+        assert!(cons.catch_up() == 3);
+        assert!(cons.len() == 1);
+        let (out, chunks) = pop_all(&mut cons);
+        assert!(out == [6.0]);
+        assert!(chunks == [(15, 0..1)]);
+        prod.push(16, &[7.0, 8.0]);
+        assert!(cons.catch_up() == 0);
+        let (out, chunks) = pop_all(&mut cons);
+        assert!(out == [7.0, 8.0]);
+        assert!(chunks == [(16, 0..2)]);
+
+        // with the ring full nothing of the overrunning cycle is kept, so the
+        // backlog goes up to the next chunk pushed
+        let (mut prod, mut cons) = port_ring(2, 1, Overflow::DropOldest);
+        prod.push(10, &[1.0, 2.0]);
+        assert!(prod.push(12, &[3.0, 4.0]) == 0);
+        assert!(cons.catch_up() == 0);
+        let mut out = [0.0];
+        cons.pop(&mut out, &mut Vec::new());
+        prod.push(14, &[5.0]);
+        assert!(cons.catch_up() == 1);
+        let (out, chunks) = pop_all(&mut cons);
+        assert!(out == [5.0]);
+        assert!(chunks == [(14, 0..1)]);
+    }
+
+    #[test]
+    fn shared_timing() {
+        let shared = SharedTiming::new();
+        assert!(shared.load().is_none());
+        let mut timing = TimingDiagnostics::new(1);
+        timing.avg_diag_cycle_time = std::time::Duration::from_micros(5);
+        timing.max_diag_cycle_time = std::time::Duration::from_micros(9);
+        shared.publish(&timing);
+        let loaded = shared.clone().load().expect("timing to be published");
+        assert!(loaded.avg_diag_cycle_time == timing.avg_diag_cycle_time);
+        assert!(loaded.max_diag_cycle_time == timing.max_diag_cycle_time);
+    }
+}
@@ -0,0 +1,431 @@
+use crate::comm::{self, Source, TimingDiagnostics, Update};
+use crate::source::{AudioSource, Pacer, SourceConfig};
+use anyhow::{bail, Result};
+use std::f64::consts::TAU;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Sine,
+    Square,
+    Saw,
+    Triangle,
+    WhiteNoise,
+    PinkNoise,
+    Impulse,
+    Sweep,
+}
+
+impl Waveform {
+    const ALL: [Waveform; 8] = [
+        Waveform::Sine,
+        Waveform::Square,
+        Waveform::Saw,
+        Waveform::Triangle,
+        Waveform::WhiteNoise,
+        Waveform::PinkNoise,
+        Waveform::Impulse,
+        Waveform::Sweep,
+    ];
+}
+
+/// Configuration of a single generator channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Signal {
+    pub waveform: Waveform,
+    /// Hz. The repetition rate for impulse trains and start frequency of sweeps
+    pub freq: f64,
+    pub amp: f32,
+    pub dc: f32,
+    /// Phase offset in cycles [0, 1)
+    pub phase: f64,
+    /// Hz. End frequency of a sweep
+    pub sweep_to: f64,
+    /// Seconds taken to sweep from `freq` to `sweep_to`
+    pub sweep_dur: f64,
+}
+
+impl Default for Signal {
+    fn default() -> Self {
+        Signal {
+            waveform: Waveform::Sine,
+            freq: 440.0,
+            amp: 0.5,
+            dc: 0.0,
+            phase: 0.0,
+            sweep_to: 20_000.0,
+            sweep_dur: 5.0,
+        }
+    }
+}
+
+/// Running state of a single generator channel
+#[derive(Debug, Clone)]
+pub struct Oscillator {
+    sample_rate: f64,
+    /// Phase accumulator in cycles [0, 1)
+    acc: f64,
+    /// Seconds into the current sweep
+    sweep_t: f64,
+    rng: u32,
+    pink: [f32; 7],
+}
+
+impl Oscillator {
+    pub fn new(sample_rate: usize, seed: u32) -> Oscillator {
+        Oscillator {
+            sample_rate: sample_rate as f64,
+            acc: 0.0,
+            sweep_t: 0.0,
+            // xorshift state must be non-zero
+            rng: seed.max(1),
+            pink: [0.0; 7],
+        }
+    }
+
+    pub fn fill(&mut self, sig: &Signal, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            *x = sig.amp * self.next(sig) + sig.dc;
+        }
+    }
+
+    /// Next unit amplitude sample
+    fn next(&mut self, sig: &Signal) -> f32 {
+        let phase = (self.acc + sig.phase).fract();
+        let x = match sig.waveform {
+            Waveform::Sine | Waveform::Sweep => (TAU * phase).sin() as f32,
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Saw => (2.0 * phase - 1.0) as f32,
+            Waveform::Triangle => (1.0 - 4.0 * (phase - 0.5).abs()) as f32,
+            Waveform::WhiteNoise => self.white(),
+            Waveform::PinkNoise => self.pink(),
+            // on the one sample of each cycle its phase wraps on
+            Waveform::Impulse => {
+                if phase < sig.freq / self.sample_rate {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        };
+
+        let freq = match sig.waveform {
+            Waveform::Sweep => {
+                // logarithmic sweep, restarting once sweep_dur has elapsed
+                let f = sig.freq * (sig.sweep_to / sig.freq).powf(self.sweep_t / sig.sweep_dur);
+                self.sweep_t += 1.0 / self.sample_rate;
+                if self.sweep_t >= sig.sweep_dur {
+                    self.sweep_t = 0.0;
+                }
+                f
+            }
+            _ => sig.freq,
+        };
+
+        self.acc += freq / self.sample_rate;
+        if self.acc >= 1.0 {
+            self.acc = self.acc.fract();
+        }
+        x
+    }
+
+    /// Uniform noise in [-1, 1) from a xorshift32 generator
+    fn white(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng as f64 / u32::MAX as f64 * 2.0 - 1.0) as f32
+    }
+
+    /// Paul Kellet's refined pink noise filter applied to white noise
+    fn pink(&mut self) -> f32 {
+        let w = self.white();
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + w * 0.0555179;
+        b[1] = 0.99332 * b[1] + w * 0.0750759;
+        b[2] = 0.96900 * b[2] + w * 0.153852;
+        b[3] = 0.86650 * b[3] + w * 0.3104856;
+        b[4] = 0.55000 * b[4] + w * 0.5329522;
+        b[5] = -0.7616 * b[5] - w * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + w * 0.5362;
+        b[6] = w * 0.115926;
+        // the filter has a gain of roughly 9 dB
+        pink * 0.11
+    }
+}
+
+/// Synthesises test signals into one ring buffer per channel at real-time pace.
+pub struct Generator {
+    pub timing: TimingDiagnostics,
+    shared_timing: comm::SharedTiming,
+    sample_rate: usize,
+    signals: Vec<Signal>,
+    port_names: Vec<String>,
+    signal_tx: Option<crossbeam_channel::Sender<(usize, Signal)>>,
+    join_handle: Option<std::thread::JoinHandle<()>>,
+    quit_tx: Option<crossbeam_channel::Sender<()>>,
+}
+
+impl Generator {
+    pub fn new(sample_rate: usize, signals: Vec<Signal>) -> Generator {
+        let port_names = (0..signals.len())
+            .map(|i| format!("gen:ch_{}", i + 1))
+            .collect();
+        Generator {
+            timing: TimingDiagnostics::new(0),
+            shared_timing: comm::SharedTiming::new(),
+            sample_rate,
+            signals,
+            port_names,
+            signal_tx: None,
+            join_handle: None,
+            quit_tx: None,
+        }
+    }
+}
+
+impl AudioSource for Generator {
+    fn start(&mut self, config: SourceConfig) -> Result<Vec<comm::PortConsumer>> {
+        if self.join_handle.is_some() {
+            bail!("Generator is already Active");
+        }
+
+        let buf_size = comm::SOURCE_BUF_SIZE;
+        let (mut rb_prods, rb_cons): (Vec<comm::PortProducer>, Vec<comm::PortConsumer>) = self
+            .signals
+            .iter()
+            .map(|_| comm::port_ring(buf_size, config.ringbuf_cycle_size, config.overflow))
+            .unzip();
+
+        let (quit_tx, quit_rx) = crossbeam_channel::bounded(1);
+        self.quit_tx = Some(quit_tx);
+        let (signal_tx, signal_rx) = crossbeam_channel::unbounded();
+        self.signal_tx = Some(signal_tx);
+
+        let mut signals = self.signals.clone();
+        let mut oscs: Vec<Oscillator> = (0..signals.len())
+            .map(|i| Oscillator::new(self.sample_rate, i as u32 + 1))
+            .collect();
+        let bus = config.bus;
+        let mut pacer = Pacer::new(buf_size, self.sample_rate);
+
+        bus.send(Update::Source(Source::Connected {
+            connected: true,
+            port_names: self.port_names.clone(),
+        }));
+
+        let shared_timing = self.shared_timing.clone();
+        let join_handle = std::thread::spawn(move || {
+            let mut timing_diagnostics = TimingDiagnostics::new(comm::TIMING_DIAGNOSTIC_CYCLES);
+            let mut buf = vec![0.0; buf_size];
+            let mut frame_time: u64 = 0;
+
+            while pacer.wait(&quit_rx) {
+                if cfg!(debug_assertions) {
+                    timing_diagnostics.record();
+                }
+
+                for (idx, sig) in signal_rx.try_iter() {
+                    signals[idx] = sig;
+                }
+
+                for ((rb, osc), sig) in rb_prods.iter_mut().zip(oscs.iter_mut()).zip(&signals) {
+                    osc.fill(sig, &mut buf);
+                    rb.push(frame_time, &buf);
+                }
+                frame_time += buf_size as u64;
+
+                if cfg!(debug_assertions) && timing_diagnostics.done() {
+                    shared_timing.publish(&timing_diagnostics);
+                }
+            }
+        });
+
+        self.join_handle = Some(join_handle);
+
+        Ok(rb_cons)
+    }
+
+    fn stop(&mut self) {
+        self.signal_tx = None;
+        if let Some(quit_tx) = self.quit_tx.take() {
+            quit_tx.send(()).expect("Generator quit tx to send");
+        }
+        if let Some(join_handle) = self.join_handle.take() {
+            join_handle
+                .join()
+                .expect("Generator join - thread has panicked");
+        }
+        println!("Generator Stopped");
+    }
+
+    fn name(&self) -> &str {
+        "Generator"
+    }
+
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn buffer_size(&self) -> u32 {
+        comm::SOURCE_BUF_SIZE as u32
+    }
+
+    fn port_names(&self) -> Vec<String> {
+        self.port_names.clone()
+    }
+
+    fn timing(&self) -> TimingDiagnostics {
+        self.timing
+    }
+
+    fn update(&mut self, _: &[Update]) {
+        if let Some(timing) = self.shared_timing.load() {
+            self.timing = timing;
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let nyquist = self.sample_rate as f64 / 2.0;
+        for (idx, sig) in self.signals.iter_mut().enumerate() {
+            let prev = *sig;
+            ui.horizontal(|ui| {
+                ui.label(&self.port_names[idx]);
+                egui::ComboBox::from_id_source(("generator waveform", idx))
+                    .selected_text(format!("{:?}", sig.waveform))
+                    .show_ui(ui, |ui| {
+                        for w in Waveform::ALL {
+                            ui.selectable_value(&mut sig.waveform, w, format!("{w:?}"));
+                        }
+                    });
+                ui.add(
+                    egui::Slider::new(&mut sig.freq, 1.0..=nyquist)
+                        .logarithmic(true)
+                        .suffix(" Hz")
+                        .text("freq"),
+                );
+                if sig.waveform == Waveform::Sweep {
+                    ui.add(
+                        egui::Slider::new(&mut sig.sweep_to, 1.0..=nyquist)
+                            .logarithmic(true)
+                            .suffix(" Hz")
+                            .text("to"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut sig.sweep_dur, 0.1..=60.0)
+                            .suffix(" s")
+                            .text("over"),
+                    );
+                }
+                ui.add(egui::Slider::new(&mut sig.amp, 0.0..=1.0).text("amp"));
+                ui.add(egui::Slider::new(&mut sig.dc, -1.0..=1.0).text("dc"));
+                ui.add(egui::Slider::new(&mut sig.phase, 0.0..=1.0).text("phase"));
+            });
+            if *sig != prev {
+                if let Some(signal_tx) = &self.signal_tx {
+                    signal_tx
+                        .send((idx, *sig))
+                        .expect("Generator signal tx to send");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portbuf;
+
+    #[test]
+    fn oscillator_waveforms() {
+        let sig = Signal {
+            waveform: Waveform::Square,
+            freq: 1_000.0,
+            amp: 0.5,
+            dc: 0.25,
+            ..Signal::default()
+        };
+        let mut osc = Oscillator::new(8_000, 1);
+        let mut buf = [0.0; 8];
+        osc.fill(&sig, &mut buf);
+        assert!(buf == [0.75, 0.75, 0.75, 0.75, -0.25, -0.25, -0.25, -0.25]);
+
+        let sig = Signal {
+            waveform: Waveform::Impulse,
+            freq: 2_000.0,
+            amp: 1.0,
+            ..Signal::default()
+        };
+        let mut osc = Oscillator::new(8_000, 1);
+        osc.fill(&sig, &mut buf);
+        assert!(buf == [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]);
+        // delayed by the phase offset
+        let mut osc = Oscillator::new(8_000, 1);
+        osc.fill(&Signal { phase: 0.25, ..sig }, &mut buf);
+        assert!(buf == [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn impulse_rate() {
+        // 3 kHz doesn't divide 8 kHz, so impulses fall 2 or 3 samples apart
+        let sig = Signal {
+            waveform: Waveform::Impulse,
+            freq: 3_000.0,
+            amp: 1.0,
+            ..Signal::default()
+        };
+        let mut osc = Oscillator::new(8_000, 1);
+        let mut buf = vec![0.0; 8_000];
+        osc.fill(&sig, &mut buf);
+        assert!(buf.iter().filter(|x| **x == 1.0).count() == 3_000);
+    }
+
+    #[test]
+    fn freq_window_amplitude() {
+        // a sine centred on a bin puts its power into that bin. With the spectrum
+        // corrected for the window's coherent gain a sine of amplitude A reads A^2
+        let sample_rate = 48_000;
+        let bin = 64;
+        let amp = 0.5;
+        let sig = Signal {
+            freq: bin as f64 * sample_rate as f64 / comm::FFT_BUF_SIZE as f64,
+            amp,
+            ..Signal::default()
+        };
+        let mut data = vec![0.0; comm::FFT_BUF_SIZE];
+        Oscillator::new(sample_rate, 1).fill(&sig, &mut data);
+
+        let (mut prod, cons) = comm::port_ring(comm::FFT_BUF_SIZE, 1, comm::OVERFLOW);
+        prod.push(0, &data);
+
+        let mut pbuf =
+            portbuf::PortBuf::new(0, "gen".to_owned(), true, sample_rate, comm::PORT_BUF_SIZE);
+        pbuf.activate(portbuf::PortBufProcessConfig {
+            rb: cons,
+            agg_bin_size: comm::AGG_SAMPLE_SIZE,
+            trigger: crate::trigger::TriggerConfig::default(),
+            meter: crate::level::MeterConfig::default(),
+            fft: portbuf::FftConfig::new(comm::FFT_BUF_SIZE),
+            bus: comm::Bus::new(egui::Context::default()),
+        })
+        .expect("pbuf to activate");
+        std::thread::sleep(comm::PORT_BUF_WAIT_DUR + std::time::Duration::from_millis(50));
+        pbuf.quit();
+
+        let spectrum = pbuf.freq_window().points;
+        assert!(spectrum.len() == comm::FFT_BUF_SIZE / 2 + 1);
+        let [freq, power] = spectrum
+            .iter()
+            .copied()
+            .max_by(|a, b| a[1].total_cmp(&b[1]))
+            .unwrap();
+        assert!(freq == sig.freq);
+        assert!((power - (amp as f64).powi(2)).abs() < 1e-5);
+    }
+}
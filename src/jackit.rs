@@ -1,46 +1,94 @@
-use crate::comm::{self, Jack, TimingDiagnostics, Update};
+use crate::comm::{self, Source, TimingDiagnostics, Update};
+use crate::source::{AudioSource, SourceConfig};
 use anyhow::{bail, Result};
-use jack;
-use ringbuf;
 use std::sync::{atomic::AtomicBool, Arc};
 
-pub struct JackItConfig {
-    pub ringbuf_cycle_size: usize,
-    pub bus: comm::Bus,
-}
-
 enum JackClient {
     Active(jack::AsyncClient<Notifications, JProcessor>),
     Passive(jack::Client),
 }
 
+/// Port changes handed to the process callback while the client is active.
+/// Ports are removed by the id they were added with, so the callback never
+/// has to drop a name.
+enum PortCmd {
+    Add(PortProc),
+    Remove(usize),
+}
+
 pub struct JackIt {
     pub timing: TimingDiagnostics,
+    /// Published by the process callback
+    shared_timing: comm::SharedTiming,
     client: Option<JackClient>,
     atomics: Vec<Arc<AtomicBool>>,
     port_names: Vec<String>,
+    /// PortProc id of each port, in port order
+    port_ids: Vec<usize>,
+    next_port_id: usize,
+    /// Ports to connect to each of our ports, in port order, once active
+    connect: Vec<String>,
+    ringbuf_cycle_size: usize,
+    overflow: comm::Overflow,
+    port_cmd_tx: Option<crossbeam_channel::Sender<PortCmd>>,
+    retired_rx: Option<crossbeam_channel::Receiver<PortProc>>,
+    /// Ports removed but not yet handed back by the process callback, which
+    /// still holds them and so counts them towards MAX_PORTS
+    retiring: usize,
 }
 
 impl JackIt {
-    pub fn new(name: &str, port_names: Vec<String>) -> JackIt {
+    pub fn new(name: &str, port_names: Vec<String>, connect: Vec<String>) -> Result<JackIt> {
         // Create client
-        let (client, _status) =
-            jack::Client::new(name, jack::ClientOptions::NO_START_SERVER).unwrap();
+        let (client, _status) = jack::Client::new(name, jack::ClientOptions::NO_START_SERVER)?;
 
         // start all ports in a Paused state until a Jack Port Connection is made
         let atomics = (0..port_names.len())
             .map(|_| Arc::new(AtomicBool::new(false)))
             .collect();
 
-        JackIt {
+        Ok(JackIt {
             timing: TimingDiagnostics::new(0),
+            shared_timing: comm::SharedTiming::new(),
             client: Some(JackClient::Passive(client)),
             atomics,
+            port_ids: (0..port_names.len()).collect(),
+            next_port_id: port_names.len(),
             port_names,
+            connect,
+            ringbuf_cycle_size: comm::RINGBUF_CYCLE_SIZE,
+            overflow: comm::OVERFLOW,
+            port_cmd_tx: None,
+            retired_rx: None,
+            retiring: 0,
+        })
+    }
+
+    fn client(&self) -> &jack::Client {
+        match &self.client {
+            Some(JackClient::Passive(c)) => c,
+            Some(JackClient::Active(a)) => a.as_client(),
+            None => panic!("JackIt has no configured client"),
         }
     }
 
-    pub fn start(&mut self, config: JackItConfig) -> Result<Vec<comm::RingConsumer>> {
+    /// Unregister ports the process callback has let go of
+    fn unregister_retired(&mut self) {
+        let (Some(retired_rx), Some(JackClient::Active(ac))) = (&self.retired_rx, &self.client)
+        else {
+            return;
+        };
+        for pp in retired_rx.try_iter() {
+            self.retiring -= 1;
+            if let Err(e) = ac.as_client().unregister_port(pp.port) {
+                eprintln!("Error: JackIt failed to unregister port {}: {e}", pp.name);
+            }
+        }
+    }
+}
+
+impl AudioSource for JackIt {
+    fn start(&mut self, config: SourceConfig) -> Result<Vec<comm::PortConsumer>> {
         let client = match self.client.take() {
             Some(JackClient::Passive(client)) => client,
             Some(JackClient::Active(_)) => bail!("JackIt is already Active"),
@@ -51,10 +99,11 @@ impl JackIt {
         let mut rb_cons = vec![];
 
         for _ in 0..self.port_names.len() {
-            let ring_buf = ringbuf::HeapRb::<f32>::new(
-                (client.buffer_size() as usize) * config.ringbuf_cycle_size,
+            let (prod, cons) = comm::port_ring(
+                client.buffer_size() as usize,
+                config.ringbuf_cycle_size,
+                config.overflow,
             );
-            let (prod, cons) = ring_buf.split();
             rb_prods.push(prod);
             rb_cons.push(cons);
         }
@@ -66,7 +115,7 @@ impl JackIt {
             .iter()
             .map(|pname| {
                 client
-                    .register_port(pname, jack::AudioIn::default())
+                    .register_port(pname, jack::AudioIn)
                     .expect("JackIt port to successfully register")
             })
             .collect();
@@ -78,29 +127,61 @@ impl JackIt {
             .collect();
 
         // consume ringbufs and cloned atomics and ports
-        let port_procs = ports
-            .into_iter()
-            .zip(rb_prods.into_iter().zip(self.atomics.clone().into_iter()))
-            .map(|(port, (rb, enabled))| PortProc { port, rb, enabled })
-            .collect();
+        let mut port_procs = Vec::with_capacity(comm::MAX_PORTS);
+        port_procs.extend(
+            ports
+                .into_iter()
+                .zip(
+                    self.port_names
+                        .clone()
+                        .into_iter()
+                        .zip(self.port_ids.clone()),
+                )
+                .zip(rb_prods.into_iter().zip(self.atomics.clone()))
+                .map(|((port, (name, id)), (rb, enabled))| PortProc {
+                    id,
+                    port,
+                    name,
+                    rb,
+                    enabled,
+                }),
+        );
+
+        let (port_cmd_tx, port_cmd_rx) = crossbeam_channel::bounded(comm::MAX_PORTS);
+        let (retired_tx, retired_rx) = crossbeam_channel::bounded(comm::MAX_PORTS);
+        self.port_cmd_tx = Some(port_cmd_tx);
+        self.retired_rx = Some(retired_rx);
+        self.ringbuf_cycle_size = config.ringbuf_cycle_size;
+        self.overflow = config.overflow;
 
         let jproc = JProcessor::new(
             port_procs,
-            config.bus.clone(),
+            port_cmd_rx,
+            retired_tx,
+            self.shared_timing.clone(),
             comm::TIMING_DIAGNOSTIC_CYCLES,
         );
 
         // Activate the client, which starts the processing.
-        self.client = Some(JackClient::Active(
-            client
-                .activate_async(Notifications { bus: config.bus }, jproc)
-                .expect("JackIt.start client.activate_async() to succeed"),
-        ));
+        let active_client = client
+            .activate_async(Notifications { bus: config.bus }, jproc)
+            .expect("JackIt.start client.activate_async() to succeed");
+
+        // a missing source port shouldn't stop us, it can be connected by hand
+        for (source_port, port_name) in self.connect.iter().zip(&self.port_names) {
+            if let Err(e) = active_client
+                .as_client()
+                .connect_ports_by_name(source_port, port_name)
+            {
+                eprintln!("Error: JackIt failed to connect {source_port} to {port_name}: {e}");
+            }
+        }
+        self.client = Some(JackClient::Active(active_client));
 
         Ok(rb_cons)
     }
 
-    pub fn stop(&mut self) {
+    fn stop(&mut self) {
         match self.client.take() {
             Some(JackClient::Active(ac)) => match ac.deactivate() {
                 Ok((client, ..)) => {
@@ -115,36 +196,95 @@ impl JackIt {
         }
     }
 
-    pub fn port_names(&self) -> Vec<String> {
-        self.port_names.clone()
+    fn name(&self) -> &str {
+        "Jack"
     }
 
-    pub fn buffer_size(&self) -> u32 {
-        let client = match &self.client {
-            Some(JackClient::Passive(c)) => c,
-            Some(JackClient::Active(a)) => a.as_client(),
-            None => panic!("JackIt has no configured client"),
+    fn supports_port_changes(&self) -> bool {
+        true
+    }
+
+    fn add_port(&mut self, name: &str) -> Result<(String, comm::PortConsumer)> {
+        let (Some(JackClient::Active(ac)), Some(port_cmd_tx)) = (&self.client, &self.port_cmd_tx)
+        else {
+            bail!("JackIt must be Active to add ports");
         };
-        client.buffer_size()
+        if self.port_names.len() + self.retiring >= comm::MAX_PORTS {
+            bail!("JackIt supports at most {} ports", comm::MAX_PORTS);
+        }
+
+        let client = ac.as_client();
+        let port = client.register_port(name, jack::AudioIn)?;
+        let full_name = port.name()?;
+        let (rb, cons) = comm::port_ring(
+            client.buffer_size() as usize,
+            self.ringbuf_cycle_size,
+            self.overflow,
+        );
+        let enabled = Arc::new(AtomicBool::new(false));
+
+        let id = self.next_port_id;
+        port_cmd_tx.try_send(PortCmd::Add(PortProc {
+            id,
+            port,
+            name: full_name.clone(),
+            rb,
+            enabled: enabled.clone(),
+        }))?;
+        self.next_port_id += 1;
+        self.port_names.push(full_name.clone());
+        self.port_ids.push(id);
+        self.atomics.push(enabled);
+
+        Ok((full_name, cons))
     }
 
-    pub fn sample_rate(&self) -> usize {
-        let client = match &self.client {
-            Some(JackClient::Passive(c)) => c,
-            Some(JackClient::Active(a)) => a.as_client(),
-            None => panic!("JackIt has no configured client"),
+    fn remove_port(&mut self, port_name: &str) -> Result<()> {
+        let Some(port_cmd_tx) = &self.port_cmd_tx else {
+            bail!("JackIt must be Active to remove ports");
         };
-        client.sample_rate()
+        let Some(idx) = self.port_names.iter().position(|n| n == port_name) else {
+            bail!("JackIt has no port {port_name}");
+        };
+
+        // the process callback hands the port back to be unregistered
+        port_cmd_tx.try_send(PortCmd::Remove(self.port_ids[idx]))?;
+        self.port_names.remove(idx);
+        self.port_ids.remove(idx);
+        self.atomics.remove(idx);
+        self.retiring += 1;
+
+        Ok(())
+    }
+
+    fn port_names(&self) -> Vec<String> {
+        self.port_names.clone()
     }
 
-    pub fn update(&mut self, updts: &Vec<Update>) {
+    fn buffer_size(&self) -> u32 {
+        self.client().buffer_size()
+    }
+
+    fn sample_rate(&self) -> usize {
+        self.client().sample_rate()
+    }
+
+    fn timing(&self) -> TimingDiagnostics {
+        self.timing
+    }
+
+    fn update(&mut self, updts: &[Update]) {
+        self.unregister_retired();
+        if let Some(timing) = self.shared_timing.load() {
+            self.timing = timing;
+        }
         for updt in updts {
             match updt {
-                Update::Jack(Jack::Connected {
+                Update::Source(Source::Connected {
                     connected,
                     port_names,
                 }) => {
-                    for port_name in port_names.into_iter() {
+                    for port_name in port_names.iter() {
                         if let Some(idx) = self.port_names.iter().position(|n| port_name == n) {
                             self.atomics
                                 .get(idx)
@@ -158,33 +298,80 @@ impl JackIt {
                         }
                     }
                 }
-                Update::Jack(Jack::TimingDiagnostics(d)) => self.timing = *d,
-                _ => (),
+                Update::Control(_) => (),
             }
         }
     }
 }
 
 struct PortProc {
+    id: usize,
     port: jack::Port<jack::AudioIn>,
-    rb: comm::RingProducer,
+    name: String,
+    rb: comm::PortProducer,
     enabled: Arc<AtomicBool>,
 }
 
 struct JProcessor {
     port_procs: Vec<PortProc>,
-    bus: comm::Bus,
+    /// Removed ports retired_tx had no room for, handed back on a later cycle
+    retiring: Vec<PortProc>,
+    port_cmd_rx: crossbeam_channel::Receiver<PortCmd>,
+    retired_tx: crossbeam_channel::Sender<PortProc>,
+    timing: comm::SharedTiming,
+    /// Jack's 32 bit frame time as of the last cycle, and the same time
+    /// extended to 64 bits so it never wraps
+    last_frame_time: Option<u32>,
+    frame_time: u64,
     timing_diagnostics: TimingDiagnostics,
 }
 
 impl JProcessor {
-    fn new(port_procs: Vec<PortProc>, bus: comm::Bus, diagnostic_proc_cycles: u32) -> JProcessor {
+    fn new(
+        port_procs: Vec<PortProc>,
+        port_cmd_rx: crossbeam_channel::Receiver<PortCmd>,
+        retired_tx: crossbeam_channel::Sender<PortProc>,
+        timing: comm::SharedTiming,
+        diagnostic_proc_cycles: u32,
+    ) -> JProcessor {
         JProcessor {
             port_procs,
-            bus,
+            retiring: Vec::with_capacity(comm::MAX_PORTS),
+            port_cmd_rx,
+            retired_tx,
+            timing,
+            last_frame_time: None,
+            frame_time: 0,
             timing_diagnostics: TimingDiagnostics::new(diagnostic_proc_cycles),
         }
     }
+
+    /// Apply pending port changes. port_procs and retiring are allocated
+    /// with room for MAX_PORTS, which the JackIt never has more than between
+    /// its ports and those still retiring, and removed ports are handed back
+    /// to the JackIt to be dropped, so nothing here allocates or frees.
+    fn apply_port_cmds(&mut self) {
+        while let Some(pp) = self.retiring.pop() {
+            if let Err(e) = self.retired_tx.try_send(pp) {
+                self.retiring.push(e.into_inner());
+                break;
+            }
+        }
+        while let Ok(cmd) = self.port_cmd_rx.try_recv() {
+            match cmd {
+                PortCmd::Add(pp) => self.port_procs.push(pp),
+                PortCmd::Remove(id) => {
+                    let Some(idx) = self.port_procs.iter().position(|pp| pp.id == id) else {
+                        continue;
+                    };
+                    if let Err(e) = self.retired_tx.try_send(self.port_procs.remove(idx)) {
+                        // keep it to hand back on a later cycle
+                        self.retiring.push(e.into_inner());
+                    }
+                }
+            }
+        }
+    }
 }
 
 impl jack::ProcessHandler for JProcessor {
@@ -193,6 +380,16 @@ impl jack::ProcessHandler for JProcessor {
             self.timing_diagnostics.record();
         }
 
+        self.apply_port_cmds();
+
+        let now = ps.last_frame_time();
+        self.frame_time = match self.last_frame_time {
+            Some(last) => self.frame_time + now.wrapping_sub(last) as u64,
+            None => now as u64,
+        };
+        self.last_frame_time = Some(now);
+        let frame_time = self.frame_time;
+
         self.port_procs
             .iter_mut()
             .filter(|pp| {
@@ -200,19 +397,13 @@ impl jack::ProcessHandler for JProcessor {
                 pp.enabled.load(std::sync::atomic::Ordering::Relaxed)
             })
             .for_each(|pp| {
-                let slice = pp.port.as_slice(ps);
-                let n_pushed = pp.rb.push_slice(slice);
-                if slice.len() != n_pushed {
-                    panic!("jackit::process did not push full slice into ringbuf: slice_len = {} pushed = {}", slice.len(), n_pushed);
-                }
+                // the ring buffer counts whatever doesn't fit as overrun
+                pp.rb.push(frame_time, pp.port.as_slice(ps));
             });
 
-        if cfg!(debug_assertions) {
-            if self.timing_diagnostics.done() {
-                self.bus.send(Update::Jack(Jack::TimingDiagnostics(
-                    self.timing_diagnostics,
-                )));
-            }
+        // sending on the Bus could block, so we only ever publish to atomics
+        if cfg!(debug_assertions) && self.timing_diagnostics.done() {
+            self.timing.publish(&self.timing_diagnostics);
         }
 
         jack::Control::Continue
@@ -278,15 +469,15 @@ impl jack::NotificationHandler for Notifications {
         port_id_b: jack::PortId,
         are_connected: bool,
     ) {
-        let port_names: Vec<String> = vec![port_id_a, port_id_b]
+        let port_names: Vec<String> = [port_id_a, port_id_b]
             .iter()
             .filter_map(|&id| client.port_by_id(id))
             .filter(|p| client.is_mine(p))
             .filter_map(|p| p.name().ok())
             .collect();
 
-        if port_names.len() > 0 {
-            self.bus.send(Update::Jack(Jack::Connected {
+        if !port_names.is_empty() {
+            self.bus.send(Update::Source(Source::Connected {
                 connected: are_connected,
                 port_names,
             }))
@@ -0,0 +1,239 @@
+/// Level at which samples count as clipped
+pub const CLIP_LEVEL: f32 = 1.0;
+
+/// Factor the true-peak meter oversamples by, as in ITU-R BS.1770 Annex 2
+const OVERSAMPLE: usize = 4;
+
+/// Taps in each phase of the true-peak interpolator
+const PHASE_TAPS: usize = 12;
+
+/// Ballistics of the level meters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeterConfig {
+    /// Time constant the RMS is integrated over, in seconds
+    pub rms_time: f64,
+    /// Seconds a peak is held before it starts to fall
+    pub hold_time: f64,
+    /// How fast peaks fall back
+    pub decay_db_per_sec: f64,
+}
+
+impl Default for MeterConfig {
+    fn default() -> Self {
+        MeterConfig {
+            rms_time: 0.3,
+            hold_time: 1.5,
+            decay_db_per_sec: 20.0,
+        }
+    }
+}
+
+/// Latest meter readings of a port, as linear amplitudes
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Levels {
+    /// Sample peak, falling at the decay rate
+    pub peak: f32,
+    /// Highest sample peak, held for the hold time before falling
+    pub peak_hold: f32,
+    /// Peak of the 4x oversampled signal, catching peaks between samples
+    pub true_peak: f32,
+    pub true_peak_hold: f32,
+    pub rms: f32,
+    /// Number of samples at or past CLIP_LEVEL so far
+    pub clips: u64,
+}
+
+/// A level with an instant attack that falls at the decay rate, and its
+/// highest value held for a while before it falls too
+#[derive(Debug, Default)]
+struct PeakHold {
+    level: f32,
+    hold: f32,
+    /// Samples left before the hold starts to fall
+    hold_left: u64,
+}
+
+impl PeakHold {
+    fn push(&mut self, x: f32, decay: f32, hold_samples: u64) {
+        self.level = x.max(self.level * decay);
+        if x >= self.hold {
+            self.hold = x;
+            self.hold_left = hold_samples;
+        } else if self.hold_left > 0 {
+            self.hold_left -= 1;
+        } else {
+            self.hold = (self.hold * decay).max(self.level);
+        }
+    }
+}
+
+/// Polyphase windowed sinc interpolator reading the peak of a signal
+/// between its samples. Phase 0 passes the samples through, delayed by half
+/// the taps, and the others land a quarter sample apart between them.
+#[derive(Debug)]
+struct TruePeak {
+    phases: [[f32; PHASE_TAPS]; OVERSAMPLE],
+    /// Last PHASE_TAPS samples, newest at idx
+    history: [f32; PHASE_TAPS],
+    idx: usize,
+}
+
+impl TruePeak {
+    fn new() -> TruePeak {
+        let taps = OVERSAMPLE * PHASE_TAPS;
+        let centre = taps as f64 / 2.0;
+        let mut phases = [[0.0; PHASE_TAPS]; OVERSAMPLE];
+        for (p, phase) in phases.iter_mut().enumerate() {
+            for (k, h) in phase.iter_mut().enumerate() {
+                let i = (p + OVERSAMPLE * k) as f64;
+                let t = (i - centre) / OVERSAMPLE as f64;
+                let sinc = match t == 0.0 {
+                    true => 1.0,
+                    false => (std::f64::consts::PI * t).sin() / (std::f64::consts::PI * t),
+                };
+                let hann = 0.5 - 0.5 * (std::f64::consts::TAU * i / taps as f64).cos();
+                *h = (sinc * hann) as f32;
+            }
+            // unity gain at DC for every phase
+            let sum: f32 = phase.iter().sum();
+            phase.iter_mut().for_each(|h| *h /= sum);
+        }
+        TruePeak {
+            phases,
+            history: [0.0; PHASE_TAPS],
+            idx: 0,
+        }
+    }
+
+    /// Load a sample and return the largest magnitude interpolated since the
+    /// last one
+    fn push(&mut self, x: f32) -> f32 {
+        self.idx = (self.idx + 1) % PHASE_TAPS;
+        self.history[self.idx] = x;
+        let mut peak = 0.0f32;
+        for phase in &self.phases {
+            let y: f32 = phase
+                .iter()
+                .enumerate()
+                .map(|(k, h)| h * self.history[(self.idx + PHASE_TAPS - k) % PHASE_TAPS])
+                .sum();
+            peak = peak.max(y.abs());
+        }
+        peak
+    }
+}
+
+/// Sample peak, true-peak, RMS and clip metering over a stream of samples
+#[derive(Debug)]
+pub struct LevelMeter {
+    /// Per sample decay of the peaks
+    decay: f32,
+    /// Weight of each sample in the RMS
+    rms_alpha: f64,
+    hold_samples: u64,
+    mean_square: f64,
+    peak: PeakHold,
+    true_peak: PeakHold,
+    oversampler: TruePeak,
+    clips: u64,
+}
+
+impl LevelMeter {
+    pub fn new(config: MeterConfig, sample_rate: usize) -> LevelMeter {
+        let sample_rate = sample_rate as f64;
+        LevelMeter {
+            decay: 10f64.powf(-config.decay_db_per_sec / 20.0 / sample_rate) as f32,
+            rms_alpha: 1.0 - (-1.0 / (config.rms_time.max(1e-6) * sample_rate)).exp(),
+            hold_samples: (config.hold_time * sample_rate).round() as u64,
+            mean_square: 0.0,
+            peak: PeakHold::default(),
+            true_peak: PeakHold::default(),
+            oversampler: TruePeak::new(),
+            clips: 0,
+        }
+    }
+
+    /// Change the ballistics, keeping the current readings
+    pub fn configure(&mut self, config: MeterConfig, sample_rate: usize) {
+        let meter = LevelMeter::new(config, sample_rate);
+        self.decay = meter.decay;
+        self.rms_alpha = meter.rms_alpha;
+        self.hold_samples = meter.hold_samples;
+    }
+
+    pub fn process(&mut self, xs: &[f32]) {
+        for x in xs {
+            let abs = x.abs();
+            if abs >= CLIP_LEVEL {
+                self.clips += 1;
+            }
+            self.peak.push(abs, self.decay, self.hold_samples);
+            let true_peak = self.oversampler.push(*x);
+            self.true_peak
+                .push(true_peak, self.decay, self.hold_samples);
+            self.mean_square += self.rms_alpha * (*x as f64 * *x as f64 - self.mean_square);
+        }
+    }
+
+    pub fn levels(&self) -> Levels {
+        Levels {
+            peak: self.peak.level,
+            peak_hold: self.peak.hold,
+            true_peak: self.true_peak.level,
+            true_peak_hold: self.true_peak.hold,
+            rms: self.mean_square.sqrt() as f32,
+            clips: self.clips,
+        }
+    }
+}
+
+/// A linear amplitude in dBFS
+pub fn to_db(x: f32) -> f32 {
+    20.0 * x.max(f32::MIN_POSITIVE).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f64, phase: f64, n: usize, sample_rate: usize) -> Vec<f32> {
+        (0..n)
+            .map(|i| {
+                let t = i as f64 / sample_rate as f64;
+                (std::f64::consts::TAU * freq * t + phase).sin() as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn level_meter() {
+        let config = MeterConfig::default();
+        let mut meter = LevelMeter::new(config, 48_000);
+        // a quarter of the sample rate 45 degrees out never samples its peaks,
+        // and 10 RMS time constants let the RMS settle
+        meter.process(&sine(
+            12_000.0,
+            std::f64::consts::FRAC_PI_4,
+            144_000,
+            48_000,
+        ));
+        let levels = meter.levels();
+        assert!((levels.peak - 0.5f32.sqrt()).abs() < 1e-3);
+        assert!((levels.true_peak - 1.0).abs() < 0.02);
+        assert!((levels.rms - 0.5f32.sqrt()).abs() < 1e-3);
+        assert!(levels.clips == 0);
+
+        // silence lets the peaks fall, the held ones only after the hold time
+        meter.process(&vec![0.0; 24_000]);
+        let levels = meter.levels();
+        assert!((to_db(levels.peak) - (-3.0 - 10.0)).abs() < 0.1);
+        assert!((levels.peak_hold - 0.5f32.sqrt()).abs() < 1e-3);
+        meter.process(&vec![0.0; 96_000]);
+        let levels = meter.levels();
+        assert!(levels.peak_hold < 0.5f32.sqrt());
+        assert!(to_db(levels.rms) < -35.0);
+
+        meter.process(&[1.0, -1.0, 0.5]);
+        assert!(meter.levels().clips == 2);
+    }
+}
@@ -0,0 +1,340 @@
+/// Seconds in each block loudness is summed over. Every BS.1770 measure is
+/// a whole number of them, and one is made per block.
+pub const BLOCK_SECS: f64 = 0.1;
+
+/// Blocks in the momentary (400ms) and short-term (3s) windows
+const MOMENTARY_BLOCKS: usize = 4;
+const SHORT_TERM_BLOCKS: usize = 30;
+
+/// Loudness below which blocks are ignored altogether, in LUFS
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// How far below the mean of the blocks past the absolute gate a block
+/// must be to be ignored, for the integrated loudness and loudness range
+const INTEGRATED_GATE: f64 = -10.0;
+const RANGE_GATE: f64 = -20.0;
+
+/// Highest loudness the gating histograms resolve and the width of their
+/// bins, in LU
+const HISTOGRAM_MAX: f64 = 10.0;
+const HISTOGRAM_STEP: f64 = 0.1;
+
+/// Loudness in LUFS of a K-weighted mean square
+fn lufs(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.max(f64::MIN_POSITIVE).log10()
+}
+
+/// Second order IIR filter in transposed direct form II
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    /// The two stages of the K-weighting filter at `sample_rate`, derived
+    /// from the analog prototypes so every rate matches the 48kHz
+    /// coefficients given in BS.1770
+    fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+        // high shelf modelling the acoustic effect of the head
+        let (f0, gain_db, q) = (
+            1_681.974_450_955_533,
+            3.999_843_853_973_347,
+            0.707_175_236_955_419_6,
+        );
+        let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.499_666_774_154_541_6);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+        // revised low-frequency B-curve high pass
+        let (f0, q) = (38.135_470_876_024_44, 0.500_327_037_323_877_3);
+        let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+        [shelf, high_pass]
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// Counts and summed mean squares of gating blocks binned by loudness, so
+/// gating over hours of blocks takes the same time as over a few
+#[derive(Debug, Clone)]
+struct Histogram {
+    counts: Vec<u64>,
+    energy: Vec<f64>,
+}
+
+impl Histogram {
+    fn new() -> Histogram {
+        let bins = ((HISTOGRAM_MAX - ABSOLUTE_GATE) / HISTOGRAM_STEP).ceil() as usize;
+        Histogram {
+            counts: vec![0; bins],
+            energy: vec![0.0; bins],
+        }
+    }
+
+    /// Loudness at the bottom of bin `i`
+    fn floor(i: usize) -> f64 {
+        ABSOLUTE_GATE + i as f64 * HISTOGRAM_STEP
+    }
+
+    /// Add a block, ignoring it if below the absolute gate
+    fn add(&mut self, mean_square: f64) {
+        let loudness = lufs(mean_square);
+        if loudness < ABSOLUTE_GATE {
+            return;
+        }
+        let i = ((loudness - ABSOLUTE_GATE) / HISTOGRAM_STEP) as usize;
+        let i = i.min(self.counts.len() - 1);
+        self.counts[i] += 1;
+        self.energy[i] += mean_square;
+    }
+
+    /// The first bin left after gating `gate` LU below the mean of every block
+    fn relative_gate(&self, gate: f64) -> Option<usize> {
+        let count: u64 = self.counts.iter().sum();
+        if count == 0 {
+            return None;
+        }
+        let mean = self.energy.iter().sum::<f64>() / count as f64;
+        let threshold = lufs(mean) + gate;
+        let first = ((threshold - ABSOLUTE_GATE) / HISTOGRAM_STEP)
+            .ceil()
+            .max(0.0) as usize;
+        Some(first.min(self.counts.len() - 1))
+    }
+
+    /// Loudness of the mean of the blocks left after gating
+    fn gated_loudness(&self, gate: f64) -> Option<f64> {
+        let first = self.relative_gate(gate)?;
+        let count: u64 = self.counts[first..].iter().sum();
+        let energy: f64 = self.energy[first..].iter().sum();
+        (count > 0).then(|| lufs(energy / count as f64))
+    }
+
+    /// Spread between the 10th and 95th percentile of the blocks left
+    /// after gating, in LU
+    fn range(&self, gate: f64) -> Option<f64> {
+        let first = self.relative_gate(gate)?;
+        let counts = &self.counts[first..];
+        let count: u64 = counts.iter().sum();
+        let percentile = |p: f64| {
+            let rank = (p * (count - 1) as f64).round() as u64;
+            let mut seen = 0;
+            let i = counts
+                .iter()
+                .position(|c| {
+                    seen += c;
+                    seen > rank
+                })
+                .unwrap_or(counts.len() - 1);
+            Histogram::floor(first + i)
+        };
+        (count > 0).then(|| percentile(0.95) - percentile(0.10))
+    }
+}
+
+/// Latest loudness readings of a port in LUFS, and its loudness range in LU.
+/// Readings yet to be made are negative infinity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    /// Over the last 400ms
+    pub momentary: f32,
+    /// Over the last 3s
+    pub short_term: f32,
+    /// Over everything since the last reset, gated to ignore silence and
+    /// quiet passages
+    pub integrated: f32,
+    pub range: f32,
+    pub max_momentary: f32,
+    pub max_short_term: f32,
+}
+
+impl Default for Loudness {
+    fn default() -> Self {
+        Loudness {
+            momentary: f32::NEG_INFINITY,
+            short_term: f32::NEG_INFINITY,
+            integrated: f32::NEG_INFINITY,
+            range: 0.0,
+            max_momentary: f32::NEG_INFINITY,
+            max_short_term: f32::NEG_INFINITY,
+        }
+    }
+}
+
+/// EBU R128 loudness metering of a single channel, as measured by
+/// ITU-R BS.1770 with K-weighting and gating
+#[derive(Debug, Clone)]
+pub struct LoudnessMeter {
+    filters: [Biquad; 2],
+    block_len: usize,
+    /// Sum of the squares of the block in progress, and its length so far
+    block_sum: f64,
+    block_n: usize,
+    /// Mean squares of the latest blocks, newest last
+    blocks: std::collections::VecDeque<f64>,
+    /// Momentary blocks for the integrated loudness and short-term ones
+    /// for the loudness range
+    momentary_hist: Histogram,
+    short_term_hist: Histogram,
+    loudness: Loudness,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: usize) -> LoudnessMeter {
+        LoudnessMeter {
+            filters: Biquad::k_weighting(sample_rate as f64),
+            block_len: (sample_rate as f64 * BLOCK_SECS).round() as usize,
+            block_sum: 0.0,
+            block_n: 0,
+            blocks: std::collections::VecDeque::with_capacity(SHORT_TERM_BLOCKS),
+            momentary_hist: Histogram::new(),
+            short_term_hist: Histogram::new(),
+            loudness: Loudness::default(),
+        }
+    }
+
+    /// Forget the integrated loudness, loudness range and maximums
+    pub fn reset(&mut self) {
+        self.momentary_hist = Histogram::new();
+        self.short_term_hist = Histogram::new();
+        self.loudness.integrated = f32::NEG_INFINITY;
+        self.loudness.range = 0.0;
+        self.loudness.max_momentary = f32::NEG_INFINITY;
+        self.loudness.max_short_term = f32::NEG_INFINITY;
+    }
+
+    /// Measure `xs`, calling `block` with the readings at the end of each
+    /// block completed
+    pub fn process(&mut self, xs: &[f32], mut block: impl FnMut(&Loudness)) {
+        for x in xs {
+            let [shelf, high_pass] = &mut self.filters;
+            let y = high_pass.process(shelf.process(*x as f64));
+            self.block_sum += y * y;
+            self.block_n += 1;
+            if self.block_n == self.block_len {
+                self.end_block();
+                block(&self.loudness);
+            }
+        }
+    }
+
+    fn end_block(&mut self) {
+        if self.blocks.len() == SHORT_TERM_BLOCKS {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back(self.block_sum / self.block_n as f64);
+        self.block_sum = 0.0;
+        self.block_n = 0;
+
+        let window = |n: usize| {
+            let blocks = self.blocks.iter().rev().take(n);
+            (self.blocks.len() >= n).then(|| blocks.sum::<f64>() / n as f64)
+        };
+        let loudness = &mut self.loudness;
+        if let Some(momentary) = window(MOMENTARY_BLOCKS) {
+            self.momentary_hist.add(momentary);
+            loudness.momentary = lufs(momentary) as f32;
+            loudness.max_momentary = loudness.max_momentary.max(loudness.momentary);
+        }
+        if let Some(short_term) = window(SHORT_TERM_BLOCKS) {
+            self.short_term_hist.add(short_term);
+            loudness.short_term = lufs(short_term) as f32;
+            loudness.max_short_term = loudness.max_short_term.max(loudness.short_term);
+        }
+        if let Some(integrated) = self.momentary_hist.gated_loudness(INTEGRATED_GATE) {
+            loudness.integrated = integrated as f32;
+        }
+        if let Some(range) = self.short_term_hist.range(RANGE_GATE) {
+            loudness.range = range as f32;
+        }
+    }
+
+    pub fn loudness(&self) -> Loudness {
+        self.loudness
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(amplitude: f32, secs: f64) -> Vec<f32> {
+        let n = (48_000.0 * secs) as usize;
+        (0..n)
+            .map(|i| amplitude * (std::f32::consts::TAU * 1_000.0 * i as f32 / 48_000.0).sin())
+            .collect()
+    }
+
+    #[test]
+    fn k_weighting() {
+        // the coefficients BS.1770 gives at 48kHz
+        let [shelf, high_pass] = Biquad::k_weighting(48_000.0);
+        let close = |a: &[f64], b: &[f64]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-8);
+        assert!(close(
+            &shelf.b,
+            &[
+                1.535_124_859_586_97,
+                -2.691_696_189_406_38,
+                1.198_392_810_852_85
+            ]
+        ));
+        assert!(close(
+            &shelf.a,
+            &[-1.690_659_293_182_41, 0.732_480_774_215_85]
+        ));
+        assert!(close(
+            &high_pass.a,
+            &[-1.990_047_454_833_98, 0.990_072_250_366_21]
+        ));
+    }
+
+    #[test]
+    fn loudness_meter() {
+        // a full scale 1kHz sine in one channel reads -3.01 LUFS
+        let mut meter = LoudnessMeter::new(48_000);
+        let mut blocks = 0;
+        meter.process(&sine(0.1, 5.0), |_| blocks += 1);
+        assert!(blocks == 50);
+        let loudness = meter.loudness();
+        assert!((loudness.momentary + 23.01).abs() < 0.05);
+        assert!((loudness.short_term + 23.01).abs() < 0.05);
+        assert!((loudness.integrated + 23.01).abs() < 0.1);
+        assert!(loudness.range < 0.2);
+
+        // a passage 20 dB down falls below the relative gate and leaves the
+        // integrated loudness alone, but widens the range
+        meter.process(&sine(0.01, 20.0), |_| ());
+        let loudness = meter.loudness();
+        assert!((loudness.momentary + 43.01).abs() < 0.05);
+        // give or take the blocks straddling the change
+        assert!((loudness.integrated + 23.01).abs() < 0.2);
+        assert!((loudness.range - 20.0).abs() < 0.5);
+        assert!((loudness.max_momentary + 23.01).abs() < 0.05);
+
+        meter.reset();
+        assert!(meter.loudness().integrated == f32::NEG_INFINITY);
+        assert!(meter.loudness().max_momentary == f32::NEG_INFINITY);
+    }
+}
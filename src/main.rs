@@ -1,69 +1,145 @@
 mod app;
+mod cli;
+mod colormap;
 mod comm;
+mod generator;
 mod jackit;
+mod level;
+mod loudness;
+mod meters;
+mod osc;
+mod phosphor;
+mod playback;
 mod portbuf;
-
+mod scope;
+mod source;
+mod spectrogram;
+mod timeseries;
+mod trigger;
+mod triple;
+mod vectorscope;
+mod window;
 
 use anyhow::Result;
 use app::TemplateApp;
+use clap::Parser;
+use source::AudioSource;
 
 fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
+    let args = cli::Args::parse();
+    args.validate()?;
+
+    if args.headless {
+        return headless(args);
+    }
+
+    // bind before the window opens so a port in use is reported, not panicked on
+    let osc_socket = match args.osc_port {
+        Some(port) => Some(osc::bind(args.osc_host, port)?),
+        None => None,
+    };
+
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
-        "scviz",
+        &args.client_name.clone(),
         native_options,
-        Box::new(|cc| {
-            let ctx = cc.egui_ctx.clone();
+        Box::new(move |cc| {
+            let bus = comm::Bus::new(cc.egui_ctx.clone());
+            let (source, port_bufs) = start(&args, &bus).expect("scviz to start");
+            if let Some(socket) = osc_socket {
+                osc::serve(socket, bus.clone());
+            }
+            Box::new(TemplateApp::new(bus, source, port_bufs, args))
+        }),
+    )?;
+    Ok(())
+}
 
-            let port_names: Vec<String> = vec!["in_1"] //, "in_2", "in_3"]
-                .iter()
-                .map(|s| s.to_string())
-                .collect();
+/// Start the source chosen by `args` and a PortBuf consuming each of its ports
+fn start(
+    args: &cli::Args,
+    bus: &comm::Bus,
+) -> Result<(Box<dyn AudioSource>, Vec<portbuf::PortBuf>)> {
+    let mut source = args.source()?;
 
-            let mut jackit = jackit::JackIt::new("scviz", port_names.clone());
+    // size of the buffer the source is configured to hand out each process cycle
+    let source_buf_size = source.buffer_size();
 
-            // size of the buffer jack is configured to hand out each process cycle
-            let jack_buf_size = jackit.buffer_size();
+    // sample rate of the source: samples / second
+    let source_sample_rate = source.sample_rate();
+    let sample_dt = 1.0 / source_sample_rate as f64;
 
-            // sample rate jack: samples / second
-            let jack_sample_rate = jackit.sample_rate();
-            let sample_dt = 1 / jack_sample_rate;
+    println!(
+        "{}: sample_rate = {}, buffer_size = {}, sample_dt = {}",
+        source.name(),
+        source_sample_rate,
+        source_buf_size,
+        sample_dt
+    );
 
-            println!(
-                "jack_sample_rate = {}, jack_buffer_size = {}, jack_sample_dt = {}",
-                jack_sample_rate, jack_buf_size, sample_dt
-            );
+    let ringbuf_consumers = source.start(source::SourceConfig {
+        bus: bus.clone(),
+        ringbuf_cycle_size: args.ringbuf_cycles,
+        overflow: args.overflow,
+    })?;
 
-            let bus = comm::Bus::new(ctx.clone());
-            let ringbuf_consumers = jackit
-                .start(jackit::JackItConfig {
-                    bus: bus.clone(),
-                    ringbuf_cycle_size: comm::RINGBUF_CYCLE_SIZE,
-                })
-                .expect("JackIt to activate");
+    let port_names = source.port_names();
+    // consume ring buffers in activated port_bufs
+    let port_bufs = ringbuf_consumers
+        .into_iter()
+        .enumerate()
+        .map(|(idx, rb)| {
+            let name = port_names[idx].clone();
+            let enabled = false;
+            let mut pb =
+                portbuf::PortBuf::new(idx, name, enabled, source_sample_rate, args.history);
+            pb.activate(portbuf::PortBufProcessConfig {
+                rb,
+                agg_bin_size: args.agg_size,
+                trigger: trigger::TriggerConfig::default(),
+                meter: level::MeterConfig::default(),
+                fft: portbuf::FftConfig::new(args.fft_size),
+                bus: bus.clone(),
+            })?;
+            Ok(pb)
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-            let port_names = jackit.port_names();
-            // consume ring buffers in activated port_bufs
-            let port_bufs: Vec<portbuf::PortBuf<{comm::PORT_BUF_SIZE}>> = ringbuf_consumers
-                .into_iter()
-                .enumerate()
-                .map(|(idx, rb)| {
-                    let name = port_names[idx].clone();
-                    let enabled = false;
-                    let mut pb = portbuf::PortBuf::new(idx, name, enabled, jack_sample_rate);
-                    pb.activate(portbuf::PortBufProcessConfig {
-                        rb,
-                        agg_bin_size: comm::AGG_SAMPLE_SIZE, 
-                        bus: bus.clone(),
-                    })
-                    .expect("PortBuf Activate to Succeed");
-                    pb
-                })
-                .collect();
+    Ok((source, port_bufs))
+}
 
-            Box::new(TemplateApp::new(bus, jackit, port_bufs))
-        }),
-    )?;
-    Ok(())
+/// Run the source and PortBufs without a window, printing the state of each
+/// port every second.
+fn headless(args: cli::Args) -> Result<()> {
+    let bus = comm::Bus::new(egui::Context::default());
+    let (mut source, mut port_bufs) = start(&args, &bus)?;
+
+    // drain the bus often so senders never block on it, but only report once a second
+    let mut last_report = std::time::Instant::now();
+    loop {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let updates = bus.updates(false);
+        source.update(&updates);
+        for pb in port_bufs.iter_mut() {
+            pb.update(&updates);
+        }
+
+        if last_report.elapsed() < std::time::Duration::from_secs(1) {
+            continue;
+        }
+        last_report = std::time::Instant::now();
+        for pb in port_bufs.iter() {
+            let (_, raw, _) = pb.curr_idx();
+            println!(
+                "{}: enabled = {}, raw idx = {raw}/{}, dropped = {}, overruns = {}, avg process time = {:?}",
+                pb.name,
+                pb.enabled,
+                pb.history,
+                pb.dropped(),
+                pb.overruns(),
+                pb.timing.avg_diag_cycle_time
+            );
+        }
+    }
 }
@@ -0,0 +1,331 @@
+use crate::app::{port_color, XPlot};
+use crate::level::{self, MeterConfig};
+use crate::loudness;
+use crate::osc::Control;
+use crate::portbuf;
+use egui::plot::{HLine, Legend, Line, LineStyle, Plot, PlotBounds, PlotPoints};
+use std::collections::HashMap;
+
+/// Range of the level meter scale in dBFS
+const DB_RANGE: (f32, f32) = (-60.0, 6.0);
+
+/// Range of the loudness meter scale in LUFS
+const LUFS_RANGE: (f32, f32) = (-60.0, 0.0);
+
+/// EBU R128 programme loudness target
+const LOUDNESS_TARGET: f32 = -23.0;
+
+/// Size of each port's meter bars
+const BAR_HEIGHT: f32 = 14.0;
+const CLIP_LIGHT_SIZE: f32 = 12.0;
+
+/// A horizontal bar of `width` spanning the values in `range`, filled up to
+/// each of `fills` in turn, marked at each of `ticks` and with a faint line
+/// at `reference`
+pub fn bar_ui(
+    ui: &mut egui::Ui,
+    width: f32,
+    (lo, hi): (f32, f32),
+    reference: f32,
+    fills: &[(f32, egui::Color32)],
+    ticks: &[(f32, egui::Color32)],
+) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(width, BAR_HEIGHT), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    let x_at = |x: f32| rect.left() + rect.width() * ((x - lo) / (hi - lo)).clamp(0.0, 1.0);
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    for (x, color) in fills {
+        let fill = egui::Rect::from_min_max(rect.left_top(), egui::pos2(x_at(*x), rect.bottom()));
+        painter.rect_filled(fill, 2.0, *color);
+    }
+    painter.vline(
+        x_at(reference),
+        rect.y_range(),
+        egui::Stroke::new(1.0, ui.visuals().weak_text_color()),
+    );
+    for (x, color) in ticks {
+        painter.vline(x_at(*x), rect.y_range(), egui::Stroke::new(2.0, *color));
+    }
+}
+
+/// Readings in a small monospace font under a meter bar
+fn readout_ui(ui: &mut egui::Ui, text: String) {
+    ui.label(egui::RichText::new(text).small().monospace());
+}
+
+/// Meters for every port. Levels as RMS bars over the falling sample peak,
+/// tick marks for the held sample and true peaks, and clip lights that stay
+/// lit until clicked. Loudness as short-term bars over the momentary, with
+/// the integrated loudness and loudness range below.
+pub struct Meters {
+    pub config: MeterConfig,
+    /// Clip counts by port_idx as of when each clip light was last reset
+    clips_seen: HashMap<usize, u64>,
+}
+
+impl Meters {
+    pub fn new() -> Self {
+        Meters {
+            config: MeterConfig::default(),
+            clips_seen: HashMap::new(),
+        }
+    }
+
+    /// Ballistics controls. Returns true when the PortBufs should be sent
+    /// the config.
+    fn config_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let prev = self.config;
+        egui::Grid::new("Meter Config").show(ui, |ui| {
+            ui.label("RMS Time");
+            ui.add(
+                egui::DragValue::new(&mut self.config.rms_time)
+                    .clamp_range(0.01..=10.0)
+                    .speed(0.01)
+                    .suffix(" s"),
+            );
+            ui.end_row();
+            ui.label("Peak Hold");
+            ui.add(
+                egui::DragValue::new(&mut self.config.hold_time)
+                    .clamp_range(0.0..=10.0)
+                    .speed(0.05)
+                    .suffix(" s"),
+            );
+            ui.end_row();
+            ui.label("Decay");
+            ui.add(
+                egui::DragValue::new(&mut self.config.decay_db_per_sec)
+                    .clamp_range(1.0..=200.0)
+                    .suffix(" dB/s"),
+            );
+            ui.end_row();
+        });
+        self.config != prev
+    }
+
+    /// A port's meter bar, with its clip light to the right
+    fn meter_ui(&mut self, ui: &mut egui::Ui, pb: &portbuf::PortBuf) {
+        let levels = pb.levels();
+        let color = port_color(pb.port_idx);
+        ui.colored_label(color, &pb.name);
+        ui.horizontal(|ui| {
+            let width = ui.available_width() - CLIP_LIGHT_SIZE - ui.spacing().item_spacing.x;
+            let true_peak_db = level::to_db(levels.true_peak_hold);
+            let true_peak_color = match true_peak_db > 0.0 {
+                true => egui::Color32::RED,
+                false => egui::Color32::YELLOW,
+            };
+            bar_ui(
+                ui,
+                width.max(1.0),
+                DB_RANGE,
+                0.0,
+                &[
+                    (level::to_db(levels.peak), color.gamma_multiply(0.4)),
+                    (level::to_db(levels.rms), color),
+                ],
+                &[
+                    (
+                        level::to_db(levels.peak_hold),
+                        ui.visuals().strong_text_color(),
+                    ),
+                    (true_peak_db, true_peak_color),
+                ],
+            );
+
+            let seen = self.clips_seen.entry(pb.port_idx).or_insert(0);
+            // a new port in the place of a removed one counts from zero
+            if levels.clips < *seen {
+                *seen = 0;
+            }
+            let clipped = levels.clips > *seen;
+            let (light, response) = ui.allocate_exact_size(
+                egui::vec2(CLIP_LIGHT_SIZE, CLIP_LIGHT_SIZE),
+                egui::Sense::click(),
+            );
+            let light_color = match clipped {
+                true => egui::Color32::RED,
+                false => ui.visuals().extreme_bg_color,
+            };
+            ui.painter()
+                .circle_filled(light.center(), CLIP_LIGHT_SIZE / 2.0, light_color);
+            let response = response.on_hover_text(format!(
+                "{} clipped samples, click to reset",
+                levels.clips - *seen
+            ));
+            if response.clicked() {
+                *seen = levels.clips;
+            }
+        });
+        readout_ui(
+            ui,
+            format!(
+                "pk {:.1}  tp {:.1}  rms {:.1} dBFS",
+                level::to_db(levels.peak_hold),
+                level::to_db(levels.true_peak_hold),
+                level::to_db(levels.rms),
+            ),
+        );
+    }
+
+    /// A port's loudness bar and readings
+    fn loudness_ui(&self, ui: &mut egui::Ui, pb: &portbuf::PortBuf) {
+        let loudness = pb.loudness();
+        let color = port_color(pb.port_idx);
+        ui.colored_label(color, &pb.name);
+        let width = ui.available_width().max(1.0);
+        bar_ui(
+            ui,
+            width,
+            LUFS_RANGE,
+            LOUDNESS_TARGET,
+            &[
+                (loudness.momentary, color.gamma_multiply(0.4)),
+                (loudness.short_term, color),
+            ],
+            &[(loudness.integrated, ui.visuals().strong_text_color())],
+        );
+        readout_ui(
+            ui,
+            format!(
+                "M {:.1}  S {:.1}  I {:.1} LUFS",
+                loudness.momentary, loudness.short_term, loudness.integrated
+            ),
+        );
+        readout_ui(
+            ui,
+            format!(
+                "LRA {:.1} LU  max M {:.1}  S {:.1}",
+                loudness.range, loudness.max_momentary, loudness.max_short_term
+            ),
+        );
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, portbufs: &[portbuf::PortBuf]) {
+        ui.heading("Levels");
+        if self.config_ui(ui) {
+            portbufs.iter().for_each(|pb| pb.set_meter(self.config));
+        }
+        ui.separator();
+        for pb in portbufs.iter().filter(|pb| pb.enabled) {
+            self.meter_ui(ui, pb);
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.heading("Loudness");
+            if ui
+                .button("Reset")
+                .on_hover_text("Restart the integrated loudness and range")
+                .clicked()
+            {
+                portbufs.iter().for_each(|pb| pb.reset_loudness());
+            }
+        });
+        for pb in portbufs.iter().filter(|pb| pb.enabled) {
+            self.loudness_ui(ui, pb);
+        }
+    }
+}
+
+/// Momentary and short-term loudness of every port over the last `span`
+/// seconds, newest at t = 0, against the loudness target
+pub struct LoudnessHistory {
+    span: f64,
+    show_momentary: bool,
+    /// Histories by port_idx held while frozen, taken on the first repaint
+    /// after freezing
+    frozen: Option<HashMap<usize, Vec<[f32; 2]>>>,
+}
+
+impl LoudnessHistory {
+    pub fn new() -> Self {
+        LoudnessHistory {
+            span: 60.0,
+            show_momentary: true,
+            frozen: None,
+        }
+    }
+}
+
+impl XPlot for LoudnessHistory {
+    fn name(&self) -> &'static str {
+        "Loudness History"
+    }
+
+    fn control(&mut self, control: &Control, _bufs: &[portbuf::PortBuf]) -> bool {
+        match control {
+            Control::Freeze(true) => {
+                self.frozen.get_or_insert_with(HashMap::new);
+            }
+            Control::Freeze(false) => self.frozen = None,
+            _ => return false,
+        }
+        true
+    }
+
+    fn plot(&mut self, ui: &mut egui::Ui, portbufs: &[portbuf::PortBuf]) {
+        let max_span = crate::comm::LOUDNESS_HISTORY as f64 * loudness::BLOCK_SECS;
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::Slider::new(&mut self.span, 10.0..=max_span)
+                    .logarithmic(true)
+                    .suffix(" s")
+                    .text("Loudness History"),
+            );
+            ui.checkbox(&mut self.show_momentary, "Momentary");
+        });
+
+        let blocks = (self.span / loudness::BLOCK_SECS).ceil() as usize;
+        let floor = LUFS_RANGE.0 as f64;
+        let mut lines = Vec::new();
+        for pb in portbufs.iter().filter(|pb| pb.enabled) {
+            let history = match &mut self.frozen {
+                Some(frozen) => frozen
+                    .entry(pb.port_idx)
+                    .or_insert_with(|| pb.loudness_history(blocks))
+                    .clone(),
+                None => pb.loudness_history(blocks),
+            };
+            let n = history.len();
+            let trace = |reading: usize| {
+                let points: Vec<[f64; 2]> = history
+                    .iter()
+                    .enumerate()
+                    .map(|(i, block)| {
+                        let t = (i as f64 + 1.0 - n as f64) * loudness::BLOCK_SECS;
+                        [t, (block[reading] as f64).max(floor)]
+                    })
+                    .collect();
+                Line::new(PlotPoints::new(points))
+            };
+            let color = port_color(pb.port_idx);
+            if self.show_momentary {
+                lines.push(
+                    trace(0)
+                        .color(color.gamma_multiply(0.5))
+                        .name(format!("{} momentary", pb.name)),
+                );
+            }
+            lines.push(trace(1).color(color).name(&pb.name));
+        }
+
+        let span = self.span;
+        Plot::new("Loudness History")
+            .legend(Legend::default())
+            .label_formatter(|name, p| format!("{name}\n{:.1} s\n{:.1} LUFS", p.x, p.y))
+            .show(ui, |plot_ui| {
+                lines.into_iter().for_each(|line| plot_ui.line(line));
+                plot_ui.hline(
+                    HLine::new(LOUDNESS_TARGET)
+                        .color(egui::Color32::GRAY)
+                        .style(LineStyle::dashed_loose())
+                        .name("Target"),
+                );
+                plot_ui.set_plot_bounds(PlotBounds::from_min_max(
+                    [-span, floor],
+                    [0.0, LUFS_RANGE.1 as f64],
+                ));
+            });
+    }
+}
@@ -0,0 +1,321 @@
+use crate::comm::{self, Update};
+use crate::scope::{TIME_WINDOW_MAX, TIME_WINDOW_MIN};
+use crate::window::{self, Window};
+use anyhow::{anyhow, bail, Result};
+use rosc::{OscMessage, OscPacket, OscType};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::sync::Arc;
+
+/// Largest packet the server reads, the most a UDP datagram can carry
+const MAX_PACKET_SIZE: usize = 65_536;
+
+/// Changes to the view an OSC client can make. Each is sent to an address
+/// under /scviz:
+///
+/// - `/scviz/time_window f` seconds shown by the scope
+/// - `/scviz/trigger/level f` in [-1, 1]
+/// - `/scviz/fft/size i`
+/// - `/scviz/fft/window s [f]` a window by name, with the beta of a kaiser
+/// - `/scviz/layout s...` the plots to show, top to bottom
+/// - `/scviz/freeze [i]` and `/scviz/unfreeze`
+/// - `/scviz/port/enable s i` enable or disable a port by name
+#[derive(Debug, Clone, PartialEq)]
+pub enum Control {
+    TimeWindow(f64),
+    TriggerLevel(f32),
+    FftSize(usize),
+    FftWindow(Window),
+    /// Plot names, as given by `plot_key`
+    Layout(Vec<String>),
+    Freeze(bool),
+    PortEnabled(String, bool),
+}
+
+/// How a plot's name is matched by `/scviz/layout`: lower case with the
+/// spaces taken out, so "Time Series" is `timeseries`
+pub fn plot_key(name: &str) -> String {
+    name.to_lowercase().replace(' ', "")
+}
+
+/// A Control received from a client. Once it has been applied the client
+/// is answered on `/scviz/ack`, or on `/scviz/error` with the reason it
+/// wasn't, either way starting with the address it was sent to.
+#[derive(Debug)]
+pub struct Request {
+    pub control: Control,
+    reply: Reply,
+}
+
+impl Request {
+    pub fn ack(&self) {
+        self.reply.send("/scviz/ack", Vec::new());
+    }
+
+    pub fn error(&self, reason: &str) {
+        self.reply
+            .send("/scviz/error", vec![OscType::String(reason.to_string())]);
+    }
+}
+
+/// Where to answer a message from
+#[derive(Debug, Clone)]
+struct Reply {
+    socket: Arc<UdpSocket>,
+    to: SocketAddr,
+    /// Address of the message being answered
+    addr: String,
+}
+
+impl Reply {
+    fn send(&self, addr: &str, args: Vec<OscType>) {
+        let packet = OscPacket::Message(OscMessage {
+            addr: addr.to_string(),
+            args: std::iter::once(OscType::String(self.addr.clone()))
+                .chain(args)
+                .collect(),
+        });
+        let sent = rosc::encoder::encode(&packet)
+            .map_err(|e| anyhow!("{e:?}"))
+            .and_then(|buf| Ok(self.socket.send_to(&buf, self.to)?));
+        if let Err(e) = sent {
+            eprintln!("Error: OSC reply to {} failed: {e}", self.to);
+        }
+    }
+}
+
+/// An argument as a finite number, whichever numeric type the client sent
+fn number(arg: Option<&OscType>) -> Option<f64> {
+    let x = match arg? {
+        OscType::Int(x) => *x as f64,
+        OscType::Long(x) => *x as f64,
+        OscType::Float(x) => *x as f64,
+        OscType::Double(x) => *x,
+        _ => return None,
+    };
+    x.is_finite().then_some(x)
+}
+
+/// An argument as a flag, sclang sending booleans as 0 or 1
+fn flag(arg: Option<&OscType>) -> Option<bool> {
+    match arg? {
+        OscType::Bool(b) => Some(*b),
+        arg => number(Some(arg)).map(|x| x != 0.0),
+    }
+}
+
+fn string(arg: Option<&OscType>) -> Option<&str> {
+    match arg? {
+        OscType::String(s) => Some(s),
+        _ => None,
+    }
+}
+
+/// A window by its label, ignoring case and dashes
+fn window(name: &str, beta: Option<f64>) -> Option<Window> {
+    let key = |s: &str| s.to_lowercase().replace('-', "");
+    let window = Window::ALL
+        .into_iter()
+        .find(|w| key(w.label()) == key(name))?;
+    Some(match (window, beta) {
+        (Window::Kaiser { .. }, Some(beta)) => Window::Kaiser { beta: beta as f32 },
+        _ => window,
+    })
+}
+
+/// The Control a message asks for
+fn parse(msg: &OscMessage) -> Result<Control> {
+    let args = &msg.args;
+    let control = match msg.addr.as_str() {
+        "/scviz/time_window" => match number(args.first()) {
+            Some(tw) if (TIME_WINDOW_MIN..=TIME_WINDOW_MAX).contains(&tw) => {
+                Control::TimeWindow(tw)
+            }
+            _ => {
+                bail!("expects a time window in seconds in [{TIME_WINDOW_MIN}, {TIME_WINDOW_MAX}]")
+            }
+        },
+        "/scviz/trigger/level" => match number(args.first()) {
+            Some(level) if (-1.0..=1.0).contains(&level) => Control::TriggerLevel(level as f32),
+            _ => bail!("expects a level in [-1, 1]"),
+        },
+        "/scviz/fft/size" => match number(args.first()) {
+            Some(size)
+                if size.fract() == 0.0
+                    && (comm::FFT_MIN_SIZE as f64..=comm::FFT_MAX_SIZE as f64).contains(&size)
+                    && (size as usize).is_power_of_two() =>
+            {
+                Control::FftSize(size as usize)
+            }
+            _ => bail!(
+                "expects a power of two in [{}, {}]",
+                comm::FFT_MIN_SIZE,
+                comm::FFT_MAX_SIZE
+            ),
+        },
+        "/scviz/fft/window" => {
+            let name = string(args.first()).unwrap_or_default();
+            match window(name, number(args.get(1))) {
+                Some(Window::Kaiser { beta }) if !window::KAISER_BETA.contains(&beta) => bail!(
+                    "expects a kaiser beta in [{}, {}]",
+                    window::KAISER_BETA.start(),
+                    window::KAISER_BETA.end()
+                ),
+                Some(window) => Control::FftWindow(window),
+                None => bail!(
+                    "expects one of {}",
+                    Window::ALL.map(|w| w.label()).join(", ")
+                ),
+            }
+        }
+        "/scviz/layout" => {
+            let names: Option<Vec<String>> = args
+                .iter()
+                .map(|arg| string(Some(arg)).map(plot_key))
+                .collect();
+            match names {
+                Some(names) if !names.is_empty() => Control::Layout(names),
+                _ => bail!("expects the names of the plots to show"),
+            }
+        }
+        "/scviz/freeze" => Control::Freeze(flag(args.first()).unwrap_or(true)),
+        "/scviz/unfreeze" => Control::Freeze(false),
+        "/scviz/port/enable" => match (string(args.first()), flag(args.get(1))) {
+            (Some(port), Some(enabled)) => Control::PortEnabled(port.to_string(), enabled),
+            _ => bail!("expects a port name and 0 or 1"),
+        },
+        _ => bail!("unknown address"),
+    };
+    Ok(control)
+}
+
+/// The UDP socket `serve` listens on
+pub fn bind(host: IpAddr, port: u16) -> Result<UdpSocket> {
+    let socket = UdpSocket::bind((host, port))
+        .map_err(|e| anyhow!("OSC server failed to bind {host}:{port}: {e}"))?;
+    println!("OSC: listening on {}", socket.local_addr()?);
+    Ok(socket)
+}
+
+/// Listen for OSC on `socket` on its own thread, passing each Control
+/// received over the Bus as an Update::Control. Messages that don't parse,
+/// or don't fit on the Bus, are answered with an error straight away.
+pub fn serve(socket: UdpSocket, bus: comm::Bus) {
+    let socket = Arc::new(socket);
+    std::thread::spawn(move || {
+        let mut buf = vec![0u8; MAX_PACKET_SIZE];
+        loop {
+            let (n, from) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) => {
+                    eprintln!("Error: OSC receive failed: {e}");
+                    continue;
+                }
+            };
+            let packet = match rosc::decoder::decode_udp(&buf[..n]) {
+                Ok((_, packet)) => packet,
+                Err(e) => {
+                    eprintln!("Error: OSC packet from {from} failed to decode: {e:?}");
+                    continue;
+                }
+            };
+            let mut packets = vec![packet];
+            while let Some(packet) = packets.pop() {
+                let msg = match packet {
+                    OscPacket::Message(msg) => msg,
+                    // bundles are applied straight away, in order
+                    OscPacket::Bundle(bundle) => {
+                        packets.extend(bundle.content.into_iter().rev());
+                        continue;
+                    }
+                };
+                let reply = Reply {
+                    socket: socket.clone(),
+                    to: from,
+                    addr: msg.addr.clone(),
+                };
+                match parse(&msg) {
+                    Ok(control) => {
+                        if let Err(Update::Control(request)) =
+                            bus.try_send(Update::Control(Request { control, reply }))
+                        {
+                            request.error("busy, try again");
+                        }
+                    }
+                    Err(e) => reply.send("/scviz/error", vec![OscType::String(e.to_string())]),
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(addr: &str, args: Vec<OscType>) -> OscMessage {
+        OscMessage {
+            addr: addr.to_string(),
+            args,
+        }
+    }
+
+    #[test]
+    fn parse_controls() {
+        let parsed = |addr, args| parse(&msg(addr, args)).ok();
+        assert!(
+            parsed("/scviz/time_window", vec![OscType::Float(0.01)])
+                == Some(Control::TimeWindow(0.01f32 as f64))
+        );
+        assert!(parsed("/scviz/time_window", vec![OscType::Int(-1)]).is_none());
+        // past what the scope can show
+        assert!(parsed("/scviz/time_window", vec![OscType::Float(1.0)]).is_none());
+        assert!(parsed("/scviz/trigger/level", vec![OscType::Float(1.5)]).is_none());
+        assert!(parsed("/scviz/trigger/level", vec![OscType::Float(f32::NAN)]).is_none());
+        assert!(
+            parsed("/scviz/fft/size", vec![OscType::Int(4096)]) == Some(Control::FftSize(4096))
+        );
+        assert!(parsed("/scviz/fft/size", vec![OscType::Int(4000)]).is_none());
+        assert!(parsed("/scviz/fft/size", vec![OscType::Int(128)]).is_none());
+        assert!(
+            parsed(
+                "/scviz/fft/window",
+                vec!["blackmanharris".to_string().into()]
+            ) == Some(Control::FftWindow(Window::BlackmanHarris))
+        );
+        assert!(
+            parsed(
+                "/scviz/fft/window",
+                vec!["Kaiser".to_string().into(), OscType::Float(5.0)]
+            ) == Some(Control::FftWindow(Window::Kaiser { beta: 5.0 }))
+        );
+        // betas the window can't be built with
+        assert!(parsed(
+            "/scviz/fft/window",
+            vec!["kaiser".to_string().into(), OscType::Float(-1.0)]
+        )
+        .is_none());
+        assert!(parsed(
+            "/scviz/fft/window",
+            vec!["kaiser".to_string().into(), OscType::Double(1e6)]
+        )
+        .is_none());
+        assert!(
+            parsed(
+                "/scviz/layout",
+                vec!["Time Series".to_string().into(), "scope".to_string().into()]
+            ) == Some(Control::Layout(vec![
+                "timeseries".to_string(),
+                "scope".to_string()
+            ]))
+        );
+        assert!(parsed("/scviz/freeze", vec![]) == Some(Control::Freeze(true)));
+        assert!(parsed("/scviz/freeze", vec![OscType::Int(0)]) == Some(Control::Freeze(false)));
+        assert!(
+            parsed(
+                "/scviz/port/enable",
+                vec!["in_2".to_string().into(), OscType::Int(1)]
+            ) == Some(Control::PortEnabled("in_2".to_string(), true))
+        );
+        assert!(parsed("/scviz/nope", vec![]).is_none());
+    }
+}
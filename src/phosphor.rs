@@ -0,0 +1,169 @@
+use egui::{Color32, ColorImage};
+
+/// A grid of how often traces have passed through each cell, decaying over
+/// time like the phosphor of an analog scope. Each segment between two
+/// points of a trace adds one hit spread over the cells it crosses, so steep
+/// edges the beam sweeps through quickly come out dim and the paths traces
+/// keep taking come out bright.
+pub struct Phosphor {
+    width: usize,
+    height: usize,
+    /// Plot coordinates covered by the first and last columns and by the
+    /// bottom and top rows
+    x_range: (f64, f64),
+    y_range: (f64, f64),
+    /// Row major, top row first
+    hits: Vec<f32>,
+}
+
+impl Phosphor {
+    pub fn new(width: usize, height: usize, x_range: (f64, f64), y_range: (f64, f64)) -> Self {
+        Phosphor {
+            width,
+            height,
+            x_range,
+            y_range,
+            hits: vec![0.0; width * height],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.hits.iter_mut().for_each(|h| *h = 0.0);
+    }
+
+    /// Scale every cell by `factor`
+    pub fn decay(&mut self, factor: f32) {
+        self.hits.iter_mut().for_each(|h| *h *= factor);
+    }
+
+    /// Cell coordinates of a point, which may be off the grid
+    fn cell(&self, [x, y]: [f64; 2]) -> (f64, f64) {
+        let (x0, x1) = self.x_range;
+        let (y0, y1) = self.y_range;
+        let c = (x - x0) / (x1 - x0) * (self.width - 1) as f64;
+        let r = (y1 - y) / (y1 - y0) * (self.height - 1) as f64;
+        (c, r)
+    }
+
+    /// How far along the segment from cell `a` to `b` it enters and leaves
+    /// the grid, as fractions of its length. None when it misses the grid.
+    fn clip(&self, a: (f64, f64), b: (f64, f64)) -> Option<(f64, f64)> {
+        if ![a.0, a.1, b.0, b.1].iter().all(|x| x.is_finite()) {
+            return None;
+        }
+        let (c_max, r_max) = ((self.width - 1) as f64, (self.height - 1) as f64);
+        let (mut t0, mut t1) = (0.0f64, 1.0f64);
+        // Liang-Barsky, each edge as p * t <= q
+        for (p, q) in [
+            (a.0 - b.0, a.0),
+            (b.0 - a.0, c_max - a.0),
+            (a.1 - b.1, a.1),
+            (b.1 - a.1, r_max - a.1),
+        ] {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return None;
+                }
+            } else if p < 0.0 {
+                t0 = t0.max(q / p);
+            } else {
+                t1 = t1.min(q / p);
+            }
+        }
+        (t0 <= t1).then_some((t0, t1))
+    }
+
+    /// Add `weight` to the cell nearest (c, r), if it's on the grid
+    fn hit(&mut self, (c, r): (f64, f64), weight: f32) {
+        let (c, r) = (c.round(), r.round());
+        if (0.0..self.width as f64).contains(&c) && (0.0..self.height as f64).contains(&r) {
+            self.hits[r as usize * self.width + c as usize] += weight;
+        }
+    }
+
+    /// Accumulate a trace of [x, y] points. Only the parts of it on the grid
+    /// leave a mark.
+    pub fn add(&mut self, points: &[[f64; 2]]) {
+        for w in points.windows(2) {
+            let ((c0, r0), (c1, r1)) = (self.cell(w[0]), self.cell(w[1]));
+            let Some((t0, t1)) = self.clip((c0, r0), (c1, r1)) else {
+                continue;
+            };
+            let steps = (c1 - c0).abs().max((r1 - r0).abs()).ceil().max(1.0);
+            let weight = 1.0 / steps as f32;
+            // the end of each segment is the start of the next
+            let last_step = ((t1 * steps).floor() as usize).min(steps as usize - 1);
+            for i in (t0 * steps).ceil() as usize..=last_step {
+                let f = i as f64 / steps;
+                self.hit((c0 + (c1 - c0) * f, r0 + (r1 - r0) * f), weight);
+            }
+        }
+        if let Some(last) = points.last() {
+            self.hit(self.cell(*last), 1.0);
+        }
+    }
+
+    pub fn max(&self) -> f32 {
+        self.hits.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Add the grid into `image` tinted by `color`, graded by the log of the
+    /// hits so rarely visited cells still show against the busiest one
+    pub fn paint(&self, image: &mut ColorImage, color: Color32) {
+        let max = self.max();
+        if max <= 0.0 {
+            return;
+        }
+        let scale = 1.0 / max.ln_1p();
+        for (pixel, hits) in image.pixels.iter_mut().zip(&self.hits) {
+            if *hits > 0.0 {
+                let lit = color.gamma_multiply(hits.ln_1p() * scale);
+                *pixel = Color32::from_rgba_premultiplied(
+                    pixel.r().saturating_add(lit.r()),
+                    pixel.g().saturating_add(lit.g()),
+                    pixel.b().saturating_add(lit.b()),
+                    pixel.a().saturating_add(lit.a()),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phosphor() {
+        let mut phosphor = Phosphor::new(11, 5, (0.0, 1.0), (-1.0, 1.0));
+        // a flat trace along the middle row, one hit per segment
+        phosphor.add(&[[0.0, 0.0], [0.5, 0.0], [1.0, 0.0]]);
+        let row = |p: &Phosphor, r: usize| p.hits[r * 11..(r + 1) * 11].to_vec();
+        assert!(row(&phosphor, 2)
+            .iter()
+            .all(|h| (h - 0.2).abs() < 1e-6 || *h == 1.0));
+        assert!((row(&phosphor, 2).iter().sum::<f32>() - 3.0).abs() < 1e-5);
+        assert!(row(&phosphor, 0).iter().all(|h| *h == 0.0));
+
+        // a vertical edge spreads its hit over the rows it crosses, and
+        // what's off the grid leaves no mark on its edge
+        phosphor.clear();
+        phosphor.add(&[[0.0, -1.0], [0.0, 1.0], [0.0, 3.0]]);
+        assert!((0..5).all(|r| (phosphor.hits[r * 11] - 0.25).abs() < 1e-6));
+        assert!((phosphor.hits.iter().sum::<f32>() - 1.25).abs() < 1e-6);
+
+        phosphor.decay(0.5);
+        assert!((phosphor.max() - 0.125).abs() < 1e-6);
+
+        let mut image = ColorImage::new([11, 5], Color32::TRANSPARENT);
+        phosphor.paint(&mut image, Color32::WHITE);
+        assert!(image[(0, 0)] == Color32::WHITE);
+        assert!(image[(5, 2)] == Color32::TRANSPARENT);
+
+        // only the part of a segment crossing the grid is stepped through
+        phosphor.clear();
+        phosphor.add(&[[0.5, -1e12], [0.5, 1e12]]);
+        assert!(phosphor.hits.iter().filter(|h| **h > 0.0).count() == 5);
+        assert!((0..5).all(|r| phosphor.hits[r * 11 + 5] > 0.0));
+    }
+}
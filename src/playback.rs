@@ -0,0 +1,324 @@
+use crate::comm::{self, Source, TimingDiagnostics, Update};
+use crate::source::{AudioSource, Pacer, SourceConfig};
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Transport state shared between the UI and the playback thread
+struct Transport {
+    playing: AtomicBool,
+    looping: AtomicBool,
+    /// Frame index of the next sample to be played
+    position: AtomicUsize,
+}
+
+/// Plays a decoded WAV or FLAC file into one ring buffer per channel at
+/// real-time pace.
+pub struct Playback {
+    pub timing: TimingDiagnostics,
+    shared_timing: comm::SharedTiming,
+    name: String,
+    sample_rate: usize,
+    channels: Arc<Vec<Vec<f32>>>,
+    port_names: Vec<String>,
+    transport: Arc<Transport>,
+    join_handle: Option<std::thread::JoinHandle<()>>,
+    quit_tx: Option<crossbeam_channel::Sender<()>>,
+}
+
+impl Playback {
+    pub fn open(path: &Path) -> Result<Playback> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+
+        let (sample_rate, channels) = match ext.as_deref() {
+            Some("wav") => decode_wav(path)?,
+            Some("flac") => decode_flac(path)?,
+            _ => bail!(
+                "Playback supports .wav and .flac files, got {}",
+                path.display()
+            ),
+        };
+
+        if channels.is_empty() || channels[0].is_empty() {
+            bail!("Playback file {} contains no samples", path.display());
+        }
+
+        let stem = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("file")
+            .to_owned();
+        let port_names = (0..channels.len())
+            .map(|i| format!("{stem}:ch_{}", i + 1))
+            .collect();
+
+        Ok(Playback {
+            timing: TimingDiagnostics::new(0),
+            shared_timing: comm::SharedTiming::new(),
+            name: format!("File ({stem})"),
+            sample_rate,
+            channels: Arc::new(channels),
+            port_names,
+            transport: Arc::new(Transport {
+                playing: AtomicBool::new(true),
+                looping: AtomicBool::new(true),
+                position: AtomicUsize::new(0),
+            }),
+            join_handle: None,
+            quit_tx: None,
+        })
+    }
+
+    /// Number of frames in the file
+    fn len(&self) -> usize {
+        self.channels[0].len()
+    }
+}
+
+impl AudioSource for Playback {
+    fn start(&mut self, config: SourceConfig) -> Result<Vec<comm::PortConsumer>> {
+        if self.join_handle.is_some() {
+            bail!("Playback is already Active");
+        }
+
+        let buf_size = comm::SOURCE_BUF_SIZE;
+        let (mut rb_prods, rb_cons): (Vec<comm::PortProducer>, Vec<comm::PortConsumer>) = self
+            .channels
+            .iter()
+            .map(|_| comm::port_ring(buf_size, config.ringbuf_cycle_size, config.overflow))
+            .unzip();
+
+        let (quit_tx, quit_rx) = crossbeam_channel::bounded(1);
+        self.quit_tx = Some(quit_tx);
+
+        let channels = self.channels.clone();
+        let transport = self.transport.clone();
+        let bus = config.bus;
+        let mut pacer = Pacer::new(buf_size, self.sample_rate);
+
+        bus.send(Update::Source(Source::Connected {
+            connected: true,
+            port_names: self.port_names.clone(),
+        }));
+
+        let shared_timing = self.shared_timing.clone();
+        let join_handle = std::thread::spawn(move || {
+            let len = channels[0].len();
+            let mut timing_diagnostics = TimingDiagnostics::new(comm::TIMING_DIAGNOSTIC_CYCLES);
+            // frame time keeps running while paused, like a jack transport
+            let mut frame_time: u64 = 0;
+
+            while pacer.wait(&quit_rx) {
+                let cycle_start = frame_time;
+                frame_time += buf_size as u64;
+                if !transport.playing.load(Ordering::Relaxed) {
+                    continue;
+                }
+
+                if cfg!(debug_assertions) {
+                    timing_diagnostics.record();
+                }
+
+                let start = transport.position.load(Ordering::Relaxed);
+                let mut pos = start.min(len);
+                let mut remaining = buf_size;
+                let mut chunk_start = cycle_start;
+                while remaining > 0 {
+                    if pos == len {
+                        if transport.looping.load(Ordering::Relaxed) {
+                            pos = 0;
+                        } else {
+                            transport.playing.store(false, Ordering::Relaxed);
+                            break;
+                        }
+                    }
+                    let n = remaining.min(len - pos);
+                    for (rb, chan) in rb_prods.iter_mut().zip(channels.iter()) {
+                        rb.push(chunk_start, &chan[pos..pos + n]);
+                    }
+                    chunk_start += n as u64;
+                    pos += n;
+                    remaining -= n;
+                }
+                // only publish our position if the UI hasn't seeked in the meantime
+                let _ = transport.position.compare_exchange(
+                    start,
+                    pos,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                );
+
+                if cfg!(debug_assertions) && timing_diagnostics.done() {
+                    shared_timing.publish(&timing_diagnostics);
+                }
+            }
+        });
+
+        self.join_handle = Some(join_handle);
+
+        Ok(rb_cons)
+    }
+
+    fn stop(&mut self) {
+        if let Some(quit_tx) = self.quit_tx.take() {
+            quit_tx.send(()).expect("Playback quit tx to send");
+        }
+        if let Some(join_handle) = self.join_handle.take() {
+            join_handle
+                .join()
+                .expect("Playback join - thread has panicked");
+        }
+        println!("Playback Stopped");
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn buffer_size(&self) -> u32 {
+        comm::SOURCE_BUF_SIZE as u32
+    }
+
+    fn port_names(&self) -> Vec<String> {
+        self.port_names.clone()
+    }
+
+    fn timing(&self) -> TimingDiagnostics {
+        self.timing
+    }
+
+    fn update(&mut self, _: &[Update]) {
+        if let Some(timing) = self.shared_timing.load() {
+            self.timing = timing;
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let sample_rate = self.sample_rate as f64;
+        let duration = self.len() as f64 / sample_rate;
+        let mut playing = self.transport.playing.load(Ordering::Relaxed);
+        let mut looping = self.transport.looping.load(Ordering::Relaxed);
+        let mut t = self.transport.position.load(Ordering::Relaxed) as f64 / sample_rate;
+
+        ui.horizontal(|ui| {
+            if ui.button(if playing { "⏸" } else { "▶" }).clicked() {
+                playing = !playing;
+                // restart from the top when play is hit at the end of the file
+                if playing && t >= duration {
+                    self.transport.position.store(0, Ordering::Relaxed);
+                }
+                self.transport.playing.store(playing, Ordering::Relaxed);
+            }
+            if ui.button("⏮").clicked() {
+                self.transport.position.store(0, Ordering::Relaxed);
+            }
+            if ui.checkbox(&mut looping, "Loop").changed() {
+                self.transport.looping.store(looping, Ordering::Relaxed);
+            }
+            ui.spacing_mut().slider_width = (ui.available_width() - 150.0).max(100.0);
+            let seek = ui.add(
+                egui::Slider::new(&mut t, 0.0..=duration)
+                    .suffix(" s")
+                    .fixed_decimals(2)
+                    .text(&self.name),
+            );
+            if seek.changed() {
+                let pos = ((t * sample_rate) as usize).min(self.len());
+                self.transport.position.store(pos, Ordering::Relaxed);
+            }
+        });
+    }
+}
+
+/// Decode a WAV file into per channel samples in [-1, 1]
+fn decode_wav(path: &Path) -> Result<(usize, Vec<Vec<f32>>)> {
+    let mut reader = hound::WavReader::open(path)
+        .with_context(|| format!("failed to open wav file {}", path.display()))?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let norm = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / norm))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    Ok((
+        spec.sample_rate as usize,
+        deinterleave(&interleaved, spec.channels as usize),
+    ))
+}
+
+/// Decode a FLAC file into per channel samples in [-1, 1]
+fn decode_flac(path: &Path) -> Result<(usize, Vec<Vec<f32>>)> {
+    let mut reader = claxon::FlacReader::open(path)
+        .with_context(|| format!("failed to open flac file {}", path.display()))?;
+    let info = reader.streaminfo();
+    let norm = (1_i64 << (info.bits_per_sample - 1)) as f32;
+    let interleaved: Vec<f32> = reader
+        .samples()
+        .map(|s| s.map(|s| s as f32 / norm))
+        .collect::<Result<_, _>>()?;
+    Ok((
+        info.sample_rate as usize,
+        deinterleave(&interleaved, info.channels as usize),
+    ))
+}
+
+fn deinterleave(interleaved: &[f32], n_channels: usize) -> Vec<Vec<f32>> {
+    let mut channels = vec![Vec::with_capacity(interleaved.len() / n_channels); n_channels];
+    for frame in interleaved.chunks_exact(n_channels) {
+        for (chan, x) in channels.iter_mut().zip(frame) {
+            chan.push(*x);
+        }
+    }
+    channels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_wav() {
+        // unique to this run, so concurrent test runs don't share the file
+        let path = std::env::temp_dir().join(format!(
+            "scviz_playback_open_wav_{}.wav",
+            std::process::id()
+        ));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44_100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).expect("wav writer to create");
+        for i in 0..100 {
+            writer.write_sample(i as i16 * 100).unwrap();
+            writer.write_sample(-(i as i16) * 100).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let playback = Playback::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        let playback = playback.expect("playback to open wav");
+
+        assert!(playback.sample_rate() == 44_100);
+        assert!(playback.len() == 100);
+        assert!(playback.port_names().len() == 2);
+        assert!(playback.channels[0][10] == 1000.0 / 32768.0);
+        assert!(playback.channels[1][10] == -1000.0 / 32768.0);
+    }
+}
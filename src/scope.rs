@@ -0,0 +1,759 @@
+use crate::app::{port_color, XPlot};
+use crate::osc::Control;
+use crate::phosphor::Phosphor;
+use crate::portbuf;
+use crate::trigger::{Edge, Mode, TriggerConfig};
+use egui::plot::{
+    HLine, Legend, Line, LineStyle, Plot, PlotBounds, PlotImage, PlotPoint, PlotPoints, Polygon,
+    VLine,
+};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// How long Auto mode waits for a trigger before free running
+const AUTO_TRIGGER_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Range of the time window in seconds
+pub const TIME_WINDOW_MIN: f64 = 5.0e-4;
+pub const TIME_WINDOW_MAX: f64 = 0.05;
+
+/// Most past sweeps XY mode keeps fading out
+const XY_MAX_SWEEPS: usize = 256;
+
+/// Columns and rows of the phosphor image
+const PHOSPHOR_SIZE: [usize; 2] = [512, 256];
+
+/// What the Scope plots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Display {
+    /// Every port against time
+    Yt,
+    /// One port against another
+    Xy,
+}
+
+impl Display {
+    const ALL: [Display; 2] = [Display::Yt, Display::Xy];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Display::Yt => "Y-T",
+            Display::Xy => "X-Y",
+        }
+    }
+}
+
+/// A time window of XY samples, kept to fade out
+struct Sweep {
+    at: Instant,
+    /// Absolute sample index one past the newest sample drawn
+    end: u64,
+    points: Vec<[f64; 2]>,
+}
+
+/// The trace last presented for a port
+struct Frame {
+    /// Points in seconds from `origin`
+    points: Vec<[f64; 2]>,
+    /// Absolute sample index drawn at t = 0, the trigger when triggered
+    origin: u64,
+    /// Gaps in the source's samples within the frame
+    gaps: Vec<(u64, u64)>,
+    /// PortBuf trigger count the frame was captured at
+    triggers: u64,
+    /// When the frame was last captured on a trigger
+    triggered_at: Instant,
+    /// The frame has been added to the port's phosphor
+    accumulated: bool,
+}
+
+pub struct Scope {
+    time_window: f64,
+    /// Fraction of the time window shown before the trigger
+    trigger_pos: f64,
+    trigger: TriggerConfig,
+    /// port_idx of the port every trace is triggered from, or each port
+    /// triggering itself when None
+    trigger_source: Option<usize>,
+    mode: Mode,
+    /// Single mode is waiting on a trigger
+    armed: bool,
+    /// Trigger counts per port_idx when Single was last armed
+    armed_at: HashMap<usize, u64>,
+    frames: HashMap<usize, Frame>,
+    display: Display,
+    /// port_idx of the ports driving X and Y, the first two enabled ports
+    /// when unset or gone
+    xy_ports: (Option<usize>, Option<usize>),
+    /// Seconds past sweeps take to fade out in XY mode
+    persistence: f64,
+    /// XY sweeps, oldest first
+    sweeps: VecDeque<Sweep>,
+    /// Accumulate every new frame into a decaying intensity image instead
+    /// of drawing only the latest
+    phosphor: bool,
+    /// Seconds for the phosphor to fall to 1/e
+    decay_time: f64,
+    /// Phosphors by port_idx
+    phosphors: HashMap<usize, Phosphor>,
+    /// Sweeps triggered ahead of each port's latest frame since the last
+    /// repaint, by port_idx, waiting to be added to its phosphor
+    unaccumulated: HashMap<usize, Vec<Vec<[f64; 2]>>>,
+    /// Sweeps added to the phosphors since they were last cleared
+    accumulated_sweeps: u64,
+    /// Time window and trigger position the phosphors were started at
+    phosphor_view: (f64, f64),
+    decayed_at: Instant,
+    texture: Option<egui::TextureHandle>,
+    /// When the display was frozen, holding what it last showed
+    frozen_at: Option<Instant>,
+}
+
+impl Scope {
+    pub fn new() -> Self {
+        Scope {
+            time_window: 0.0025, // 400hz sine wave period
+            trigger_pos: 0.5,
+            trigger: TriggerConfig::default(),
+            trigger_source: None,
+            mode: Mode::Auto,
+            armed: false,
+            armed_at: HashMap::new(),
+            frames: HashMap::new(),
+            display: Display::Yt,
+            xy_ports: (None, None),
+            persistence: 0.5,
+            sweeps: VecDeque::new(),
+            phosphor: false,
+            decay_time: 1.0,
+            phosphors: HashMap::new(),
+            unaccumulated: HashMap::new(),
+            accumulated_sweeps: 0,
+            phosphor_view: (0.0, 0.0),
+            decayed_at: Instant::now(),
+            texture: None,
+            frozen_at: None,
+        }
+    }
+
+    /// Trigger controls. Returns true when the PortBufs should be sent the config.
+    fn trigger_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        portbufs: &[portbuf::PortBuf],
+        status: &str,
+    ) -> bool {
+        let prev = self.trigger;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Mode")
+                .selected_text(self.mode.label())
+                .show_ui(ui, |ui| {
+                    for mode in Mode::ALL {
+                        ui.selectable_value(&mut self.mode, mode, mode.label());
+                    }
+                });
+            let source_name = self
+                .trigger_source
+                .and_then(|idx| portbufs.iter().find(|pb| pb.port_idx == idx))
+                .map_or("Each Port", |pb| pb.name.as_str());
+            egui::ComboBox::from_label("Source")
+                .selected_text(source_name)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.trigger_source, None, "Each Port");
+                    for pb in portbufs {
+                        ui.selectable_value(&mut self.trigger_source, Some(pb.port_idx), &pb.name);
+                    }
+                });
+            egui::ComboBox::from_label("Edge")
+                .selected_text(self.trigger.edge.label())
+                .show_ui(ui, |ui| {
+                    for edge in Edge::ALL {
+                        ui.selectable_value(&mut self.trigger.edge, edge, edge.label());
+                    }
+                });
+            ui.add(
+                egui::DragValue::new(&mut self.trigger.level)
+                    .clamp_range(-1.0..=1.0)
+                    .speed(0.005),
+            );
+            ui.label("Level");
+            ui.add(
+                egui::DragValue::new(&mut self.trigger.hysteresis)
+                    .clamp_range(0.0..=0.5)
+                    .speed(0.001),
+            );
+            ui.label("Hysteresis");
+            ui.add(
+                egui::DragValue::new(&mut self.trigger.holdoff)
+                    .clamp_range(0.0..=1.0)
+                    .speed(0.0001)
+                    .suffix(" s"),
+            );
+            ui.label("Holdoff");
+            if self.mode == Mode::Single && ui.button("Re-arm").clicked() {
+                self.armed = true;
+                self.armed_at = portbufs
+                    .iter()
+                    .map(|pb| (pb.port_idx, pb.last_trigger().0))
+                    .collect();
+            }
+            ui.label(status);
+        });
+        self.trigger != prev
+    }
+
+    /// Capture new frames for `targets` if `source` has triggered since the
+    /// last one and enough samples have arrived to fill the window after the
+    /// trigger. Every target is cut from the same clock index as the trigger.
+    fn capture(
+        &mut self,
+        source: &portbuf::PortBuf,
+        targets: &[&portbuf::PortBuf],
+        n: usize,
+    ) -> bool {
+        let seen = match self.mode {
+            Mode::Single if !self.armed => return false,
+            Mode::Single => self.armed_at.get(&source.port_idx).copied().unwrap_or(0),
+            _ => self.frames.get(&source.port_idx).map_or(0, |f| f.triggers),
+        };
+        let pre = (n as f64 * self.trigger_pos).round() as usize;
+        let (triggers, trigger) = match source.complete_trigger(seen, n - pre) {
+            Some(trigger) => trigger,
+            None => return false,
+        };
+        let start = match trigger.checked_sub(pre as u64) {
+            Some(start) => start,
+            None => return false,
+        };
+        // other ports may not have processed up to the trigger's window yet
+        let t_start = -(pre as f64) / source.sample_rate as f64;
+        let windows: Option<Vec<_>> = targets
+            .iter()
+            .map(|pb| pb.sample_window(start, n, t_start))
+            .collect();
+        let windows = match windows {
+            Some(windows) => windows,
+            None => return false,
+        };
+        if self.phosphor && self.mode != Mode::Single {
+            // the phosphor takes every sweep since the last frame, not only the latest
+            let earlier = source
+                .complete_triggers(seen, n - pre)
+                .into_iter()
+                .filter(|(count, _)| *count < triggers)
+                .filter_map(|(_, idx)| idx.checked_sub(pre as u64));
+            for start in earlier {
+                for pb in targets {
+                    if let Some(points) = pb.sample_window(start, n, t_start) {
+                        self.unaccumulated
+                            .entry(pb.port_idx)
+                            .or_default()
+                            .push(points);
+                    }
+                }
+            }
+        }
+        for (pb, points) in targets.iter().zip(windows) {
+            self.frames.insert(
+                pb.port_idx,
+                Frame {
+                    points,
+                    origin: trigger,
+                    gaps: pb.gaps(start, start + n as u64),
+                    triggers,
+                    triggered_at: Instant::now(),
+                    accumulated: false,
+                },
+            );
+        }
+        true
+    }
+
+    /// Untriggered frames of the latest samples every port has processed
+    fn free_run(&mut self, ports: &[&portbuf::PortBuf], all: &[&portbuf::PortBuf], n: usize) {
+        let end = all.iter().map(|pb| pb.samples_end()).min().unwrap_or(0);
+        let pre = (n as f64 * self.trigger_pos).round() as usize;
+        for pb in ports {
+            let t_start = -(pre as f64) / pb.sample_rate as f64;
+            let (start, points) = match end.checked_sub(n as u64) {
+                Some(start) => pb.sample_window(start, n, t_start).map(|p| (start, p)),
+                None => None,
+            }
+            .unwrap_or_else(|| {
+                let start = pb.samples_end().saturating_sub(n as u64);
+                (start, pb.time_window(self.time_window, t_start))
+            });
+            let frame = self.frames.entry(pb.port_idx).or_insert(Frame {
+                points: Vec::new(),
+                origin: 0,
+                gaps: Vec::new(),
+                triggers: 0,
+                triggered_at: Instant::now() - AUTO_TRIGGER_TIMEOUT,
+                accumulated: false,
+            });
+            // repaints without new samples redraw the same frame
+            let origin = start + pre as u64;
+            frame.accumulated &= frame.origin == origin;
+            frame.points = points;
+            frame.origin = origin;
+            frame.gaps = pb.gaps(start, start + n as u64);
+        }
+    }
+
+    /// Capture new frames of the enabled ports, or free run them, as the
+    /// trigger mode has it. Returns true when a trigger was captured.
+    fn acquire(&mut self, enabled: &[&portbuf::PortBuf], n: usize) -> bool {
+        let source = self
+            .trigger_source
+            .and_then(|idx| enabled.iter().find(|pb| pb.port_idx == idx))
+            .copied();
+        let mut triggered = false;
+        let mut untriggered = Vec::new();
+        match source {
+            Some(source) => {
+                triggered = self.capture(source, enabled, n);
+                if !triggered {
+                    untriggered = enabled.to_vec();
+                }
+            }
+            None => {
+                for pb in enabled {
+                    if self.capture(pb, &[pb], n) {
+                        triggered = true;
+                    } else {
+                        untriggered.push(*pb);
+                    }
+                }
+            }
+        }
+        if self.mode == Mode::Auto {
+            // free run until the trigger comes back
+            let stale: Vec<&portbuf::PortBuf> = untriggered
+                .into_iter()
+                .filter(|pb| {
+                    self.frames
+                        .get(&pb.port_idx)
+                        .map_or(true, |f| f.triggered_at.elapsed() > AUTO_TRIGGER_TIMEOUT)
+                })
+                .collect();
+            self.free_run(&stale, enabled, n);
+        }
+        if triggered && self.mode == Mode::Single {
+            self.armed = false;
+        }
+        triggered
+    }
+
+    fn clear_phosphors(&mut self) {
+        self.phosphors.clear();
+        self.accumulated_sweeps = 0;
+    }
+
+    /// Decay the phosphors, add the sweeps they haven't seen and return an
+    /// image of them all
+    fn accumulate(&mut self, ports: &[&portbuf::PortBuf]) -> egui::ColorImage {
+        let view = (self.time_window, self.trigger_pos);
+        if view != self.phosphor_view {
+            self.clear_phosphors();
+            self.phosphor_view = view;
+        }
+        let factor = (-self.decayed_at.elapsed().as_secs_f64() / self.decay_time).exp();
+        self.decayed_at = Instant::now();
+        self.phosphors
+            .values_mut()
+            .for_each(|phosphor| phosphor.decay(factor as f32));
+
+        let pre_t = self.trigger_pos * self.time_window;
+        let x_range = (-pre_t, self.time_window - pre_t);
+        let mut image = egui::ColorImage::new(PHOSPHOR_SIZE, egui::Color32::TRANSPARENT);
+        for pb in ports {
+            let frame = match self.frames.get_mut(&pb.port_idx) {
+                Some(frame) => frame,
+                None => continue,
+            };
+            let phosphor = self.phosphors.entry(pb.port_idx).or_insert_with(|| {
+                let [width, height] = PHOSPHOR_SIZE;
+                Phosphor::new(width, height, x_range, (-1.1, 1.1))
+            });
+            let earlier = self.unaccumulated.remove(&pb.port_idx).unwrap_or_default();
+            for points in earlier {
+                phosphor.add(&points);
+                self.accumulated_sweeps += 1;
+            }
+            if !frame.accumulated {
+                phosphor.add(&frame.points);
+                frame.accumulated = true;
+                self.accumulated_sweeps += 1;
+            }
+            phosphor.paint(&mut image, port_color(pb.port_idx));
+        }
+        image
+    }
+
+    /// Port selection and persistence for XY mode
+    fn xy_ui(&mut self, ui: &mut egui::Ui, portbufs: &[portbuf::PortBuf]) {
+        let name = |idx: Option<usize>| {
+            idx.and_then(|idx| portbufs.iter().find(|pb| pb.port_idx == idx))
+                .map_or("-", |pb| pb.name.as_str())
+        };
+        let prev = self.xy_ports;
+        let (x, y) = &mut self.xy_ports;
+        for (label, port) in [("X", x), ("Y", y)] {
+            egui::ComboBox::from_label(label)
+                .selected_text(name(*port))
+                .show_ui(ui, |ui| {
+                    for pb in portbufs {
+                        ui.selectable_value(port, Some(pb.port_idx), &pb.name);
+                    }
+                });
+        }
+        if self.xy_ports != prev {
+            self.sweeps.clear();
+        }
+        ui.add(
+            egui::Slider::new(&mut self.persistence, 0.0..=5.0)
+                .suffix(" s")
+                .text("Persistence"),
+        );
+    }
+
+    /// One port against another, with the sweeps of the last `persistence`
+    /// seconds fading out behind the latest
+    fn plot_xy(&mut self, ui: &mut egui::Ui, portbufs: &[portbuf::PortBuf]) {
+        let find =
+            |idx: Option<usize>| idx.and_then(|idx| portbufs.iter().find(|pb| pb.port_idx == idx));
+        let mut enabled = portbufs.iter().filter(|pb| pb.enabled);
+        let x = find(self.xy_ports.0).or_else(|| enabled.next());
+        let y = find(self.xy_ports.1)
+            .or_else(|| enabled.find(|pb| Some(pb.port_idx) != x.map(|x| x.port_idx)));
+        let (x, y) = match (x, y) {
+            (Some(x), Some(y)) => (x, y),
+            _ => {
+                ui.label("Needs two ports");
+                return;
+            }
+        };
+        self.xy_ports = (Some(x.port_idx), Some(y.port_idx));
+
+        let n = x.samples_in(self.time_window);
+        let samples = match self.frozen_at {
+            Some(_) => None,
+            None => portbuf::aligned_samples(&[x, y], n),
+        };
+        if let Some((start, samples)) = samples {
+            let end = start + n as u64;
+            if self.sweeps.back().map_or(true, |sweep| sweep.end != end) {
+                let points = samples[0]
+                    .iter()
+                    .zip(&samples[1])
+                    .map(|(x, y)| [*x as f64, *y as f64])
+                    .collect();
+                self.sweeps.push_back(Sweep {
+                    at: Instant::now(),
+                    end,
+                    points,
+                });
+            }
+        }
+        // frozen sweeps stay as faded as they were
+        let now = self.frozen_at.unwrap_or_else(Instant::now);
+        let persistence = Duration::from_secs_f64(self.persistence);
+        while self.sweeps.len() > 1
+            && (self.sweeps.len() > XY_MAX_SWEEPS
+                || self
+                    .sweeps
+                    .front()
+                    .map_or(false, |s| now.duration_since(s.at) > persistence))
+        {
+            self.sweeps.pop_front();
+        }
+        if self.sweeps.len() > 1 && self.frozen_at.is_none() {
+            // keep fading while the source is quiet
+            ui.ctx().request_repaint();
+        }
+
+        let color = port_color(x.port_idx);
+        let newest = self.sweeps.len().saturating_sub(1);
+        let lines: Vec<Line> = self
+            .sweeps
+            .iter()
+            .enumerate()
+            .map(|(i, sweep)| {
+                let fade = match i == newest {
+                    true => 1.0,
+                    false => {
+                        1.0 - now.duration_since(sweep.at).as_secs_f32() / self.persistence as f32
+                    }
+                };
+                Line::new(PlotPoints::new(sweep.points.clone()))
+                    .color(color.gamma_multiply(fade.clamp(0.0, 1.0)))
+            })
+            .collect();
+        let (x_name, y_name) = (x.name.clone(), y.name.clone());
+        Plot::new("Scope XY")
+            .data_aspect(1.0)
+            .include_x(-1.1)
+            .include_x(1.1)
+            .include_y(-1.1)
+            .include_y(1.1)
+            .label_formatter(move |_, p| format!("{x_name} {:.4}\n{y_name} {:.4}", p.x, p.y))
+            .show(ui, |plot_ui| {
+                lines.into_iter().for_each(|line| plot_ui.line(line));
+            });
+    }
+}
+
+impl XPlot for Scope {
+    fn name(&self) -> &'static str {
+        "Scope"
+    }
+
+    fn control(&mut self, control: &Control, portbufs: &[portbuf::PortBuf]) -> bool {
+        match control {
+            Control::TimeWindow(tw) => self.time_window = *tw,
+            Control::TriggerLevel(level) => {
+                self.trigger.level = *level;
+                portbufs.iter().for_each(|pb| pb.set_trigger(self.trigger));
+            }
+            Control::Freeze(true) => {
+                self.frozen_at.get_or_insert_with(Instant::now);
+            }
+            Control::Freeze(false) => {
+                if let Some(frozen_at) = self.frozen_at.take() {
+                    // pick up fading where it was left
+                    let frozen_for = frozen_at.elapsed();
+                    self.decayed_at += frozen_for;
+                    self.sweeps
+                        .iter_mut()
+                        .for_each(|sweep| sweep.at += frozen_for);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn plot(&mut self, ui: &mut egui::Ui, portbufs: &[portbuf::PortBuf]) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Display")
+                .selected_text(self.display.label())
+                .show_ui(ui, |ui| {
+                    for display in Display::ALL {
+                        ui.selectable_value(&mut self.display, display, display.label());
+                    }
+                });
+            ui.add(
+                egui::Slider::new(&mut self.time_window, TIME_WINDOW_MIN..=TIME_WINDOW_MAX)
+                    .text("Time Window"),
+            );
+            match self.display {
+                Display::Yt => {
+                    ui.add(
+                        egui::Slider::new(&mut self.trigger_pos, 0.0..=1.0)
+                            .custom_formatter(|x, _| format!("{:.0}%", x * 100.0))
+                            .text("Trigger Position"),
+                    );
+                    ui.checkbox(&mut self.phosphor, "Phosphor");
+                    if self.phosphor {
+                        ui.add(
+                            egui::Slider::new(&mut self.decay_time, 0.05..=10.0)
+                                .logarithmic(true)
+                                .suffix(" s")
+                                .text("Decay"),
+                        );
+                        if ui.button("Clear").clicked() {
+                            self.phosphors.values_mut().for_each(Phosphor::clear);
+                            self.accumulated_sweeps = 0;
+                        }
+                        ui.label(format!("{} sweeps", self.accumulated_sweeps));
+                    }
+                }
+                Display::Xy => self.xy_ui(ui, portbufs),
+            }
+        });
+        if self.display == Display::Xy {
+            self.plot_xy(ui, portbufs);
+            return;
+        }
+
+        let enabled: Vec<&portbuf::PortBuf> = portbufs.iter().filter(|pb| pb.enabled).collect();
+        let n = enabled
+            .first()
+            .map_or(0, |pb| pb.samples_in(self.time_window));
+        let triggered = match self.frozen_at {
+            Some(_) => false,
+            None => self.acquire(&enabled, n),
+        };
+
+        let status = match (self.mode, triggered, self.armed) {
+            _ if self.frozen_at.is_some() => "Frozen",
+            (_, true, _) => "Trig'd",
+            (Mode::Auto, false, _) => "Auto",
+            (Mode::Normal, false, _) => "Waiting",
+            (Mode::Single, false, true) => "Armed",
+            (Mode::Single, false, false) => "Stopped",
+        };
+        if self.trigger_ui(ui, portbufs, status) {
+            portbufs.iter().for_each(|pb| pb.set_trigger(self.trigger));
+        }
+
+        let pre_t = self.trigger_pos * self.time_window;
+        let image = match self.phosphor {
+            // hold the image as it was
+            true if self.frozen_at.is_some() => self.texture.as_ref().map(|texture| {
+                PlotImage::new(
+                    texture,
+                    PlotPoint::new(self.time_window / 2.0 - pre_t, 0.0),
+                    [self.time_window as f32, 2.2],
+                )
+            }),
+            true => {
+                let image = self.accumulate(&enabled);
+                match &mut self.texture {
+                    Some(texture) => texture.set(image, egui::TextureOptions::LINEAR),
+                    None => {
+                        self.texture = Some(ui.ctx().load_texture(
+                            "phosphor",
+                            image,
+                            egui::TextureOptions::LINEAR,
+                        ))
+                    }
+                }
+                // the phosphor keeps fading between new frames
+                ui.ctx().request_repaint();
+                self.texture.as_ref().map(|texture| {
+                    PlotImage::new(
+                        texture,
+                        PlotPoint::new(self.time_window / 2.0 - pre_t, 0.0),
+                        [self.time_window as f32, 2.2],
+                    )
+                })
+            }
+            false => {
+                self.clear_phosphors();
+                None
+            }
+        };
+        let mut lines = Vec::new();
+        let mut gaps = Vec::new();
+        let mut origin = None;
+        for pb in portbufs.iter().filter(|pb| pb.enabled) {
+            let frame = match self.frames.get(&pb.port_idx) {
+                Some(frame) => frame,
+                None => continue,
+            };
+            if image.is_none() {
+                lines.push(
+                    Line::new(PlotPoints::new(frame.points.clone()))
+                        .color(port_color(pb.port_idx))
+                        .name(&pb.name),
+                );
+            }
+            let sample_rate = pb.sample_rate as f64;
+            let to_t = |idx: u64| (idx as f64 - frame.origin as f64) / sample_rate;
+            for (start, end) in frame.gaps.iter() {
+                let (t0, t1) = (
+                    to_t(*start).max(-pre_t),
+                    to_t(*end).min(self.time_window - pre_t),
+                );
+                gaps.push(
+                    Polygon::new(PlotPoints::new(vec![
+                        [t0, -1.1],
+                        [t1, -1.1],
+                        [t1, 1.1],
+                        [t0, 1.1],
+                    ]))
+                    .color(egui::Color32::RED)
+                    .fill_alpha(0.15)
+                    .name("Gap"),
+                );
+            }
+            origin.get_or_insert(frame.origin as f64 / sample_rate);
+        }
+        let level = self.trigger.level as f64;
+        let origin = origin.unwrap_or(0.0);
+        Plot::new("Scope")
+            .legend(Legend::default())
+            .label_formatter(move |name, p| {
+                // source time of the point alongside its time from the trigger
+                format!("{name}\n{:.6} s\n@ {:.6} s", p.x, origin + p.x)
+            })
+            .show(ui, |plot_ui| {
+                if let Some(image) = image {
+                    plot_ui.image(image);
+                }
+                gaps.into_iter().for_each(|gap| plot_ui.polygon(gap));
+                lines.into_iter().for_each(|line| plot_ui.line(line));
+                plot_ui.hline(
+                    HLine::new(level)
+                        .color(egui::Color32::GRAY)
+                        .style(LineStyle::dashed_loose())
+                        .name("Trigger Level"),
+                );
+                plot_ui.vline(
+                    VLine::new(0.0)
+                        .color(egui::Color32::GRAY)
+                        .style(LineStyle::dashed_loose())
+                        .name("Trigger Position"),
+                );
+                plot_ui.set_plot_bounds(PlotBounds::from_min_max(
+                    [-pre_t, -1.1],
+                    [self.time_window - pre_t, 1.1],
+                ))
+            });
+    }
+
+    fn port_added(&mut self, pb: &portbuf::PortBuf) {
+        pb.set_trigger(self.trigger);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comm;
+    use crate::level::MeterConfig;
+
+    #[test]
+    fn phosphor_takes_every_sweep() {
+        let mut pbuf = portbuf::PortBuf::new(0, "name".to_owned(), true, 1_000, 64);
+        // room for a push of each period
+        let (mut prod, cons) = comm::port_ring(8, 8, comm::Overflow::DropNewest);
+        pbuf.activate(portbuf::PortBufProcessConfig {
+            rb: cons,
+            agg_bin_size: 4,
+            trigger: TriggerConfig::default(),
+            meter: MeterConfig::default(),
+            fft: portbuf::FftConfig::new(256),
+            bus: comm::Bus::new(egui::Context::default()),
+        })
+        .expect("pbuf to activate");
+        let wait = || std::thread::sleep(comm::PORT_BUF_WAIT_DUR * 2);
+
+        let mut scope = Scope::new();
+        scope.phosphor = true;
+        scope.time_window = 0.008;
+        let n = pbuf.samples_in(scope.time_window);
+        let repaint = |scope: &mut Scope| {
+            scope.acquire(&[&pbuf], n);
+            scope.accumulate(&[&pbuf]);
+            scope.accumulated_sweeps
+        };
+
+        // five rising edges, 8 samples apart, between two repaints
+        let period = [-1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0];
+        for i in 0..5 {
+            prod.push(i * 8, &period);
+        }
+        prod.push(40, &[-1.0; 4]);
+        wait();
+        assert!(pbuf.complete_triggers(0, n / 2).len() == 5);
+        assert!(repaint(&mut scope) == 5);
+        // nothing new to add
+        assert!(repaint(&mut scope) == 5);
+        prod.push(44, &[1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]);
+        wait();
+        assert!(repaint(&mut scope) == 6);
+        pbuf.quit();
+    }
+}
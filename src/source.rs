@@ -0,0 +1,202 @@
+use crate::comm::{self, TimingDiagnostics, Update};
+use anyhow::{bail, Result};
+
+pub struct SourceConfig {
+    pub ringbuf_cycle_size: usize,
+    pub bus: comm::Bus,
+    /// What to drop when a port's ring buffer is full
+    pub overflow: comm::Overflow,
+}
+
+/// A producer of audio samples. Each port of the source is handed out as a
+/// ring buffer consumer on `start`, which are then consumed by `PortBuf`s.
+/// Samples are pushed tagged with the source's frame time, so they can be
+/// lined up across ports and gaps in them noticed.
+/// Sources report connection changes through the `comm::Bus` given in the
+/// `SourceConfig`, and share their timing through a `comm::SharedTiming`.
+pub trait AudioSource {
+    /// Activate the source, returning one ring buffer consumer per port in
+    /// the same order as `port_names`.
+    fn start(&mut self, config: SourceConfig) -> Result<Vec<comm::PortConsumer>>;
+
+    fn stop(&mut self);
+
+    /// Human readable name of the backend, shown in the diagnostics panel
+    fn name(&self) -> &str;
+
+    /// Samples / second
+    fn sample_rate(&self) -> usize;
+
+    /// Number of samples handed out each process cycle
+    fn buffer_size(&self) -> u32;
+
+    fn port_names(&self) -> Vec<String>;
+
+    /// Whether ports can be added and removed while the source is running
+    fn supports_port_changes(&self) -> bool {
+        false
+    }
+
+    /// Register a new port on a running source, returning its full name and
+    /// the ring buffer consumer it feeds.
+    fn add_port(&mut self, name: &str) -> Result<(String, comm::PortConsumer)> {
+        bail!("{} does not support adding port {name}", self.name())
+    }
+
+    fn remove_port(&mut self, port_name: &str) -> Result<()> {
+        bail!("{} does not support removing port {port_name}", self.name())
+    }
+
+    fn timing(&self) -> TimingDiagnostics;
+
+    fn update(&mut self, updts: &[Update]);
+
+    /// Source specific controls, drawn in the top panel of the app
+    fn ui(&mut self, _ui: &mut egui::Ui) {}
+}
+
+/// Paces a software source thread against the wall clock, handing out one
+/// process cycle at a time. Sleeping towards a deadline rather than for a
+/// fixed duration keeps processing time from accumulating as drift.
+pub struct Pacer {
+    cycle_dur: std::time::Duration,
+    deadline: std::time::Instant,
+}
+
+impl Pacer {
+    pub fn new(buffer_size: usize, sample_rate: usize) -> Pacer {
+        Pacer {
+            cycle_dur: std::time::Duration::from_secs_f64(buffer_size as f64 / sample_rate as f64),
+            deadline: std::time::Instant::now(),
+        }
+    }
+
+    /// Block until the next cycle is due. Returns false once `quit_rx` has
+    /// received or disconnected.
+    pub fn wait(&mut self, quit_rx: &crossbeam_channel::Receiver<()>) -> bool {
+        self.deadline += self.cycle_dur;
+        let wait = self
+            .deadline
+            .saturating_duration_since(std::time::Instant::now());
+        match quit_rx.recv_timeout(wait) {
+            Ok(_) => false,
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => false,
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::portbuf;
+
+    /// Pushes a fixed ramp into every port on start
+    struct RampSource {
+        port_names: Vec<String>,
+        len: usize,
+        /// Frame time of the first sample of the ramp
+        start: u64,
+    }
+
+    impl AudioSource for RampSource {
+        fn start(&mut self, config: SourceConfig) -> Result<Vec<comm::PortConsumer>> {
+            let ramp: Vec<f32> = (0..self.len).map(|i| i as f32).collect();
+            let cons = self
+                .port_names
+                .iter()
+                .map(|_| {
+                    let (mut prod, cons) =
+                        comm::port_ring(self.len, config.ringbuf_cycle_size, config.overflow);
+                    // as though the source had already been running for a while
+                    prod.push(self.start, &ramp);
+                    cons
+                })
+                .collect();
+            config.bus.send(Update::Source(comm::Source::Connected {
+                connected: true,
+                port_names: self.port_names.clone(),
+            }));
+            Ok(cons)
+        }
+
+        fn stop(&mut self) {}
+
+        fn name(&self) -> &str {
+            "Ramp"
+        }
+
+        fn sample_rate(&self) -> usize {
+            1_000
+        }
+
+        fn buffer_size(&self) -> u32 {
+            self.len as u32
+        }
+
+        fn port_names(&self) -> Vec<String> {
+            self.port_names.clone()
+        }
+
+        fn timing(&self) -> TimingDiagnostics {
+            TimingDiagnostics::new(0)
+        }
+
+        fn update(&mut self, _: &[Update]) {}
+    }
+
+    #[test]
+    fn source_feeds_port_buf() {
+        let mut source = RampSource {
+            port_names: vec!["ramp_1".to_owned(), "ramp_2".to_owned()],
+            len: 8,
+            start: 92,
+        };
+        let bus = comm::Bus::new(egui::Context::default());
+        let consumers = source
+            .start(SourceConfig {
+                ringbuf_cycle_size: 2,
+                bus: bus.clone(),
+                overflow: comm::OVERFLOW,
+            })
+            .expect("RampSource to start");
+        assert!(consumers.len() == 2);
+
+        let mut pbufs: Vec<portbuf::PortBuf> = consumers
+            .into_iter()
+            .enumerate()
+            .map(|(idx, rb)| {
+                let mut pb = portbuf::PortBuf::new(
+                    idx,
+                    source.port_names()[idx].clone(),
+                    false,
+                    source.sample_rate(),
+                    16,
+                );
+                pb.activate(portbuf::PortBufProcessConfig {
+                    rb,
+                    agg_bin_size: 4,
+                    trigger: crate::trigger::TriggerConfig::default(),
+                    meter: crate::level::MeterConfig::default(),
+                    fft: portbuf::FftConfig::new(16),
+                    bus: bus.clone(),
+                })
+                .expect("PortBuf to activate");
+                pb
+            })
+            .collect();
+
+        std::thread::sleep(comm::PORT_BUF_WAIT_DUR + std::time::Duration::from_millis(5));
+
+        let updates = bus.updates(false);
+        for pb in pbufs.iter_mut() {
+            pb.update(&updates);
+            pb.quit();
+            assert!(pb.enabled);
+            // both ports place the ramp at the source's frame time
+            assert!(pb.samples_end() == 100);
+            let points = pb.sample_window(92, 2, 0.0).expect("92..94 to be held");
+            assert!(points.iter().map(|p| p[1]).eq([0.0, 1.0]));
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use crate::app::XPlot;
+use crate::colormap::ColorMap;
+use crate::osc::Control;
+use crate::portbuf;
+use egui::plot::{Plot, PlotBounds, PlotImage, PlotPoint};
+use std::collections::VecDeque;
+
+/// Number of frequency rows in the spectrogram image
+const IMAGE_ROWS: usize = 512;
+
+/// Scrolling time-frequency view of the successive FFT frames of one port
+pub struct Spectrogram {
+    sample_rate: f64,
+    /// Port being drawn, the first enabled port when unset or gone
+    port_name: Option<String>,
+    color_map: ColorMap,
+    db_min: f32,
+    db_max: f32,
+    log_freq: bool,
+    /// Number of frames shown
+    history: usize,
+    /// Power spectra, oldest first
+    frames: VecDeque<Vec<f32>>,
+    /// PortBuf frame count as of the newest frame in `frames`
+    frame_count: u64,
+    fft: Option<portbuf::FftConfig>,
+    texture: Option<egui::TextureHandle>,
+    dirty: bool,
+    /// Stop pulling new frames
+    frozen: bool,
+}
+
+impl Spectrogram {
+    pub fn new(sample_rate: f64) -> Self {
+        Spectrogram {
+            sample_rate,
+            port_name: None,
+            color_map: ColorMap::Viridis,
+            db_min: -120.0,
+            db_max: 0.0,
+            log_freq: false,
+            history: 256,
+            frames: VecDeque::new(),
+            frame_count: 0,
+            fft: None,
+            texture: None,
+            dirty: true,
+            frozen: false,
+        }
+    }
+
+    fn controls_ui(&mut self, ui: &mut egui::Ui, portbufs: &[portbuf::PortBuf]) {
+        let prev = (
+            self.port_name.clone(),
+            self.color_map,
+            self.db_min,
+            self.db_max,
+            self.log_freq,
+            self.history,
+        );
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Port")
+                .selected_text(self.port_name.as_deref().unwrap_or("-"))
+                .show_ui(ui, |ui| {
+                    for pb in portbufs {
+                        ui.selectable_value(&mut self.port_name, Some(pb.name.clone()), &pb.name);
+                    }
+                });
+            egui::ComboBox::from_label("Colour Map")
+                .selected_text(self.color_map.label())
+                .show_ui(ui, |ui| {
+                    for color_map in ColorMap::ALL {
+                        ui.selectable_value(&mut self.color_map, color_map, color_map.label());
+                    }
+                });
+            ui.add(
+                egui::DragValue::new(&mut self.db_min)
+                    .clamp_range(-200.0..=self.db_max - 1.0)
+                    .suffix(" dB"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.db_max)
+                    .clamp_range(self.db_min + 1.0..=20.0)
+                    .suffix(" dB"),
+            );
+            ui.label("Range");
+            ui.checkbox(&mut self.log_freq, "Log Freq");
+            ui.add(egui::Slider::new(&mut self.history, 16..=2048).text("History"));
+        });
+        let curr = (
+            self.port_name.clone(),
+            self.color_map,
+            self.db_min,
+            self.db_max,
+            self.log_freq,
+            self.history,
+        );
+        if curr.0 != prev.0 {
+            self.clear();
+        }
+        // pull_frames doesn't run while frozen, so trim here as well
+        while self.frames.len() > self.history {
+            self.frames.pop_front();
+        }
+        self.dirty |= curr != prev;
+    }
+
+    fn clear(&mut self) {
+        self.frames.clear();
+        self.frame_count = 0;
+        self.dirty = true;
+    }
+
+    /// Pull any frames computed since the last repaint
+    fn pull_frames(&mut self, pb: &portbuf::PortBuf) {
+        let (fft, _) = pb.fft_info();
+        if self.fft != Some(fft) {
+            self.fft = Some(fft);
+            self.clear();
+        }
+        let (frame_count, frames) = pb.fft_frames(self.frame_count);
+        if frame_count < self.frame_count {
+            self.clear();
+        }
+        let bins = fft.size / 2 + 1;
+        for frame in frames.into_iter().filter(|f| f.len() == bins) {
+            self.frames.push_back(frame);
+            self.dirty = true;
+        }
+        while self.frames.len() > self.history {
+            self.frames.pop_front();
+        }
+        self.frame_count = frame_count;
+    }
+
+    /// Frequency range drawn on the y axis, in log10(Hz) with log_freq
+    fn freq_range(&self, fft_size: usize) -> (f64, f64) {
+        let nyquist = self.sample_rate / 2.0;
+        if self.log_freq {
+            let bin_size = self.sample_rate / fft_size as f64;
+            (bin_size.log10(), nyquist.log10())
+        } else {
+            (0.0, nyquist)
+        }
+    }
+
+    fn image(&self, fft_size: usize) -> egui::ColorImage {
+        let bin_size = self.sample_rate / fft_size as f64;
+        let last_bin = fft_size / 2;
+        let (lo, hi) = self.freq_range(fft_size);
+        let bin = |row_edge: usize| {
+            let y = lo + (hi - lo) * row_edge as f64 / IMAGE_ROWS as f64;
+            let freq = if self.log_freq { 10f64.powf(y) } else { y };
+            ((freq / bin_size).round() as usize).min(last_bin)
+        };
+        // bins each row spans, top row highest, so a tone between the rows
+        // still shows. Rows narrower than a bin take the nearest one.
+        let row_bins: Vec<std::ops::RangeInclusive<usize>> = (0..IMAGE_ROWS)
+            .map(|r| {
+                let (top, bottom) = (bin(IMAGE_ROWS - r), bin(IMAGE_ROWS - r - 1));
+                // the bottom bin of each row is the top of the one below,
+                // but for the bottom row
+                let bottom = if r + 1 < IMAGE_ROWS {
+                    bottom + 1
+                } else {
+                    bottom
+                };
+                bottom.min(top)..=top
+            })
+            .collect();
+
+        let background = self.color_map.color(0.0);
+        let mut image = egui::ColorImage::new([self.history, IMAGE_ROWS], background);
+        // newest frame in the rightmost column
+        let skip = self.frames.len().saturating_sub(self.history);
+        let first_col = self.history.saturating_sub(self.frames.len());
+        let db_span = self.db_max - self.db_min;
+        for (c, frame) in self.frames.iter().skip(skip).enumerate() {
+            for (r, bins) in row_bins.iter().enumerate() {
+                let power = frame[bins.clone()].iter().copied().fold(0.0, f32::max);
+                let db = 10.0 * power.max(f32::MIN_POSITIVE).log10();
+                image[(first_col + c, r)] = self.color_map.color((db - self.db_min) / db_span);
+            }
+        }
+        image
+    }
+}
+
+impl XPlot for Spectrogram {
+    fn name(&self) -> &'static str {
+        "Spectrogram"
+    }
+
+    fn control(&mut self, control: &Control, _bufs: &[portbuf::PortBuf]) -> bool {
+        match control {
+            Control::Freeze(frozen) => self.frozen = *frozen,
+            _ => return false,
+        }
+        true
+    }
+
+    fn plot(&mut self, ui: &mut egui::Ui, portbufs: &[portbuf::PortBuf]) {
+        self.controls_ui(ui, portbufs);
+
+        let pb = match &self.port_name {
+            Some(name) => portbufs.iter().find(|pb| &pb.name == name),
+            None => None,
+        }
+        .or_else(|| portbufs.iter().find(|pb| pb.enabled));
+        let pb = match pb {
+            Some(pb) => pb,
+            None => {
+                ui.label("No port to draw");
+                return;
+            }
+        };
+        if self.port_name.as_ref() != Some(&pb.name) {
+            self.port_name = Some(pb.name.clone());
+            self.clear();
+        }
+        if !self.frozen {
+            self.pull_frames(pb);
+        }
+        let fft = match self.fft {
+            Some(fft) => fft,
+            None => return,
+        };
+
+        if self.dirty || self.texture.is_none() {
+            let image = self.image(fft.size);
+            match &mut self.texture {
+                Some(texture) => texture.set(image, egui::TextureOptions::LINEAR),
+                None => {
+                    self.texture = Some(ui.ctx().load_texture(
+                        "spectrogram",
+                        image,
+                        egui::TextureOptions::LINEAR,
+                    ))
+                }
+            }
+            self.dirty = false;
+        }
+        let texture = match &self.texture {
+            Some(texture) => texture,
+            None => return,
+        };
+
+        // seconds of signal the history spans, newest at t = 0
+        let span = (self.history * fft.overlap.hop(fft.size)) as f64 / self.sample_rate;
+        let (lo, hi) = self.freq_range(fft.size);
+        let log_freq = self.log_freq;
+        let image = PlotImage::new(
+            texture,
+            PlotPoint::new(-span / 2.0, (lo + hi) / 2.0),
+            [span as f32, (hi - lo) as f32],
+        );
+        Plot::new("Spectrogram")
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .y_grid_spacer(move |input| {
+                if log_freq {
+                    crate::app::log_freq_grid(input)
+                } else {
+                    (egui::plot::log_grid_spacer(10))(input)
+                }
+            })
+            .y_axis_formatter(move |y, _| {
+                crate::app::format_hz(if log_freq { 10f64.powf(y) } else { y })
+            })
+            .label_formatter(move |_, p| {
+                let freq = if log_freq { 10f64.powf(p.y) } else { p.y };
+                format!("{:.3} s\n{}", p.x, crate::app::format_hz(freq))
+            })
+            .show(ui, |plot_ui| {
+                plot_ui.image(image);
+                plot_ui.set_plot_bounds(PlotBounds::from_min_max([-span, lo], [0.0, hi]));
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_show_their_loudest_bin() {
+        // 1 Hz bins, so each of the rows spans 4 of the 2049 bins
+        let fft_size = 4096;
+        let mut spectrogram = Spectrogram::new(fft_size as f64);
+        let mut frame = vec![0.0; fft_size / 2 + 1];
+        // row 261 spans bins 1001..=1004, so its top edge misses this one
+        frame[1002] = 1.0;
+        spectrogram.frames.push_back(frame);
+
+        let image = spectrogram.image(fft_size);
+        let col = spectrogram.history - 1;
+        let (peak, floor) = (
+            spectrogram.color_map.color(1.0),
+            spectrogram.color_map.color(0.0),
+        );
+        assert!(image[(col, 261)] == peak);
+        assert!(image[(col, 260)] == floor);
+        assert!(image[(col, 262)] == floor);
+        assert!(image.pixels.iter().filter(|p| **p == peak).count() == 1);
+    }
+}
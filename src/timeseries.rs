@@ -0,0 +1,327 @@
+use crate::app::{port_color, XPlot};
+use crate::osc::Control;
+use crate::portbuf;
+use egui::plot::{Legend, Line, Plot, PlotBounds, PlotPoints, PlotUi, Polygon};
+
+/// Most points drawn per pixel of plot width before switching to a coarser
+/// resolution
+const POINTS_PER_PIXEL: f64 = 2.0;
+
+/// What a TimeSeries draws its traces from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resolution {
+    Raw,
+    /// Bins of a time series level
+    Level(usize),
+}
+
+/// The index into `resolutions`, given as (samples per point, absolute
+/// sample index held from) finest first, of the finest one drawing no more
+/// than POINTS_PER_PIXEL that still reaches back to `start`. Falls back to
+/// the finest that isn't too dense, then to the coarsest.
+fn choose_resolution(samples_per_pixel: f64, start: u64, resolutions: &[(usize, u64)]) -> usize {
+    let sparse = |(size, _): &&(usize, u64)| *size as f64 * POINTS_PER_PIXEL >= samples_per_pixel;
+    let mut candidates = resolutions.iter().enumerate().filter(|(_, r)| sparse(r));
+    candidates
+        .clone()
+        .find(|(_, (_, held_from))| *held_from <= start)
+        .or_else(|| candidates.next())
+        .map_or(resolutions.len() - 1, |(i, _)| i)
+}
+
+/// Combine runs of adjacent bins of `bin_size` samples so no more than
+/// `max_bins` are left, as (absolute sample index of the middle, agg). Zoomed
+/// out past the coarsest level this keeps the shapes drawn bounded by the
+/// plot's width rather than the span.
+fn merge_bins(
+    bins: &[(u64, portbuf::Agg)],
+    bin_size: usize,
+    max_bins: usize,
+) -> Vec<(f64, portbuf::Agg)> {
+    let run = (bins.len() + max_bins.max(1) - 1) / max_bins.max(1);
+    bins.chunks(run.max(1))
+        .map(|run| {
+            let n = run.len() as f32;
+            let agg = portbuf::Agg {
+                min: run.iter().map(|(_, a)| a.min).fold(f32::INFINITY, f32::min),
+                max: run
+                    .iter()
+                    .map(|(_, a)| a.max)
+                    .fold(f32::NEG_INFINITY, f32::max),
+                mean: run.iter().map(|(_, a)| a.mean).sum::<f32>() / n,
+                rms: (run.iter().map(|(_, a)| a.rms * a.rms).sum::<f32>() / n).sqrt(),
+            };
+            let mid = run[0].0 as f64 + (run.len() * bin_size) as f64 / 2.0;
+            (mid, agg)
+        })
+        .collect()
+}
+
+/// Fill opacity of the min/max envelope
+const ENVELOPE_ALPHA: f32 = 0.25;
+
+/// Fill opacity of the RMS band, drawn over the envelope
+const RMS_ALPHA: f32 = 0.45;
+
+/// A band between `lo` and `hi` through (t, lo, hi) points, as one
+/// trapezoid per pair of points since egui only fills convex polygons
+fn band(points: &[(f64, f64, f64)], color: egui::Color32, alpha: f32, name: &str) -> Vec<Polygon> {
+    points
+        .windows(2)
+        .map(|w| {
+            let ((t0, lo0, hi0), (t1, lo1, hi1)) = (w[0], w[1]);
+            Polygon::new(PlotPoints::new(vec![
+                [t0, lo0],
+                [t1, lo1],
+                [t1, hi1],
+                [t0, hi0],
+            ]))
+            .color(color)
+            .width(0.0)
+            .fill_alpha(alpha)
+            .name(name)
+        })
+        .collect()
+}
+
+/// History of the ports over a zoomable span, newest sample at t = 0. Zoomed
+/// in it draws the raw samples, and zoomed out an overview waveform from the
+/// time series level whose bins suit the zoom: a filled min/max envelope
+/// with the RMS around the mean as a denser band inside it.
+pub struct TimeSeries {
+    /// Seconds shown when the view is reset
+    span: f64,
+    show_envelope: bool,
+    show_mean: bool,
+    show_rms: bool,
+    /// Set the plot bounds to the last `span` seconds on the next repaint
+    reset: bool,
+    /// Resolution the last repaint was drawn at
+    resolution: Option<(Resolution, usize)>,
+    frozen: bool,
+    /// Sample index held at t = 0 while frozen, taken on the first repaint
+    /// after freezing
+    frozen_now: Option<u64>,
+}
+
+impl TimeSeries {
+    pub fn new() -> Self {
+        TimeSeries {
+            span: 10.0,
+            show_envelope: true,
+            show_mean: false,
+            show_rms: true,
+            reset: true,
+            resolution: None,
+            frozen: false,
+            frozen_now: None,
+        }
+    }
+
+    fn controls_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut self.span)
+                    .clamp_range(0.001..=86_400.0)
+                    .speed(0.1)
+                    .suffix(" s"),
+            );
+            ui.label("Span");
+            self.reset |= ui.button("Reset View").clicked();
+            ui.checkbox(&mut self.show_envelope, "Envelope");
+            ui.checkbox(&mut self.show_mean, "Mean");
+            ui.checkbox(&mut self.show_rms, "RMS");
+            match self.resolution {
+                Some((Resolution::Raw, _)) => ui.label("Raw samples"),
+                Some((Resolution::Level(level), bin_size)) => {
+                    ui.label(format!("Level {level}, {bin_size} samples a bin"))
+                }
+                None => ui.label("-"),
+            };
+        });
+    }
+
+    /// Draw one port's samples in [start, end) at `resolution`, with `now`
+    /// the absolute sample index drawn at t = 0, and bins merged down to
+    /// `max_points`
+    fn draw(
+        &self,
+        plot_ui: &mut PlotUi,
+        pb: &portbuf::PortBuf,
+        resolution: Resolution,
+        (start, end): (u64, u64),
+        now: u64,
+        max_points: usize,
+    ) {
+        let sample_rate = pb.sample_rate as f64;
+        let to_t = |idx: f64| (idx - now as f64) / sample_rate;
+        let color = port_color(pb.port_idx);
+        let level = match resolution {
+            Resolution::Raw => {
+                let start = start.max(pb.samples_start());
+                let n = end.saturating_sub(start) as usize;
+                if let Some(points) = pb.sample_window(start, n, to_t(start as f64)) {
+                    plot_ui.line(
+                        Line::new(PlotPoints::new(points))
+                            .color(color)
+                            .name(&pb.name),
+                    );
+                }
+                return;
+            }
+            Resolution::Level(level) => level,
+        };
+
+        let bins = merge_bins(
+            &pb.series(level, start, end),
+            pb.series_bin_size(level),
+            max_points,
+        );
+        let points = |stat: fn(&portbuf::Agg) -> (f64, f64)| -> Vec<(f64, f64, f64)> {
+            bins.iter()
+                .map(|(mid, agg)| {
+                    let (lo, hi) = stat(agg);
+                    (to_t(*mid), lo, hi)
+                })
+                .collect()
+        };
+        if self.show_envelope {
+            let envelope = points(|agg| (agg.min as f64, agg.max as f64));
+            let name = format!("{} envelope", pb.name);
+            for polygon in band(&envelope, color, ENVELOPE_ALPHA, &name) {
+                plot_ui.polygon(polygon);
+            }
+        }
+        if self.show_rms {
+            // around the mean, kept within the envelope
+            let rms = points(|agg| {
+                let (mean, rms) = (agg.mean as f64, agg.rms as f64);
+                (
+                    (mean - rms).max(agg.min as f64),
+                    (mean + rms).min(agg.max as f64),
+                )
+            });
+            let name = format!("{} rms", pb.name);
+            for polygon in band(&rms, color, RMS_ALPHA, &name) {
+                plot_ui.polygon(polygon);
+            }
+        }
+        if self.show_mean {
+            let mean: Vec<[f64; 2]> = points(|agg| (agg.mean as f64, agg.mean as f64))
+                .into_iter()
+                .map(|(t, mean, _)| [t, mean])
+                .collect();
+            plot_ui.line(Line::new(PlotPoints::new(mean)).color(color).name(&pb.name));
+        }
+    }
+}
+
+impl XPlot for TimeSeries {
+    fn name(&self) -> &'static str {
+        "Time Series"
+    }
+
+    fn control(&mut self, control: &Control, _bufs: &[portbuf::PortBuf]) -> bool {
+        match control {
+            Control::Freeze(frozen) => {
+                self.frozen = *frozen;
+                self.frozen_now = None;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn plot(&mut self, ui: &mut egui::Ui, portbufs: &[portbuf::PortBuf]) {
+        self.controls_ui(ui);
+
+        let enabled: Vec<&portbuf::PortBuf> = portbufs.iter().filter(|pb| pb.enabled).collect();
+        // every port drawn against the clock of the one furthest behind
+        let mut now = enabled.iter().map(|pb| pb.samples_end()).min().unwrap_or(0);
+        if self.frozen {
+            now = *self.frozen_now.get_or_insert(now);
+        }
+        let width = ui.available_width().max(1.0) as f64;
+        let reset = std::mem::take(&mut self.reset);
+        let span = self.span;
+        let plot = Plot::new("TimeSeries")
+            .legend(Legend::default())
+            .label_formatter(|name, p| format!("{name}\n{:.6} s\n{:.4}", p.x, p.y));
+        let resolution = plot.show(ui, |plot_ui| {
+            let bounds = if reset {
+                let bounds = PlotBounds::from_min_max([-span, -1.1], [0.0, 1.1]);
+                plot_ui.set_plot_bounds(bounds);
+                bounds
+            } else {
+                plot_ui.plot_bounds()
+            };
+            let pb = enabled.first()?;
+            let sample_rate = pb.sample_rate as f64;
+            let to_idx = |t: f64| (now as f64 + t * sample_rate).clamp(0.0, now as f64) as u64;
+            let (start, end) = (to_idx(bounds.min()[0]), to_idx(bounds.max()[0]));
+            let samples_per_pixel = bounds.width() * sample_rate / width;
+
+            let resolutions: Vec<(usize, u64)> = std::iter::once((1, pb.samples_start()))
+                .chain(
+                    (0..crate::comm::SERIES_LEVELS)
+                        .map(|level| (pb.series_bin_size(level), pb.series_start(level))),
+                )
+                .collect();
+            let chosen = choose_resolution(samples_per_pixel, start, &resolutions);
+            let resolution = match chosen {
+                0 => Resolution::Raw,
+                r => Resolution::Level(r - 1),
+            };
+            let max_points = (POINTS_PER_PIXEL * width).ceil() as usize;
+            for pb in enabled.iter() {
+                self.draw(plot_ui, pb, resolution, (start, end), now, max_points);
+            }
+            Some((resolution, resolutions[chosen].0))
+        });
+        self.resolution = resolution.inner;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolution_by_zoom() {
+        // raw samples held from 900, then levels of 4 and 32 samples a bin
+        let resolutions = [(1, 900), (4, 500), (32, 0)];
+        assert!(choose_resolution(1.0, 950, &resolutions) == 0);
+        assert!(choose_resolution(2.0, 950, &resolutions) == 0);
+        assert!(choose_resolution(3.0, 950, &resolutions) == 1);
+        assert!(choose_resolution(64.0, 950, &resolutions) == 2);
+        // the finer resolutions don't reach back far enough
+        assert!(choose_resolution(1.0, 600, &resolutions) == 1);
+        assert!(choose_resolution(1.0, 100, &resolutions) == 2);
+        // zoomed out past every level
+        assert!(choose_resolution(1e6, 100, &resolutions) == 2);
+        // nothing held that far back, so the finest that isn't too dense
+        let resolutions = [(1, 900), (4, 500)];
+        assert!(choose_resolution(3.0, 100, &resolutions) == 1);
+    }
+
+    #[test]
+    fn merged_bins() {
+        let agg = |x: f32| portbuf::Agg {
+            min: -x,
+            max: x,
+            mean: x / 2.0,
+            rms: x,
+        };
+        let bins: Vec<(u64, portbuf::Agg)> = (0..5).map(|i| (i * 4, agg(i as f32))).collect();
+        // few enough already
+        assert!(merge_bins(&bins, 4, 5).len() == 5);
+        let merged = merge_bins(&bins, 4, 2);
+        assert!(merged.len() == 2);
+        // runs of 3 bins, the last one short
+        assert!(merged[0].0 == 6.0 && merged[1].0 == 16.0);
+        assert!(merged[0].1.min == -2.0 && merged[0].1.max == 2.0);
+        assert!(merged[0].1.mean == 0.5);
+        assert!((merged[0].1.rms - (5.0f32 / 3.0).sqrt()).abs() < 1e-6);
+        assert!(merged[1].1.min == -4.0 && merged[1].1.mean == 1.75);
+    }
+}
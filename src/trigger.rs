@@ -0,0 +1,167 @@
+/// Signal direction through the trigger level that fires the trigger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Either,
+}
+
+impl Edge {
+    pub const ALL: [Edge; 3] = [Edge::Rising, Edge::Falling, Edge::Either];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Edge::Rising => "Rising",
+            Edge::Falling => "Falling",
+            Edge::Either => "Either",
+        }
+    }
+}
+
+/// When the Scope presents a new frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// On every trigger, free running when none arrive
+    Auto,
+    /// Only on a trigger, holding the last frame in between
+    Normal,
+    /// On the first trigger after being armed
+    Single,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 3] = [Mode::Auto, Mode::Normal, Mode::Single];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Mode::Auto => "Auto",
+            Mode::Normal => "Normal",
+            Mode::Single => "Single",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TriggerConfig {
+    pub edge: Edge,
+    pub level: f32,
+    /// How far past the level the signal has to swing back before the
+    /// trigger re-arms, so noise around the level can't re-fire it
+    pub hysteresis: f32,
+    /// Seconds after firing in which the trigger ignores crossings
+    pub holdoff: f64,
+}
+
+impl Default for TriggerConfig {
+    fn default() -> Self {
+        TriggerConfig {
+            edge: Edge::Rising,
+            level: 0.0,
+            hysteresis: 0.01,
+            holdoff: 0.0,
+        }
+    }
+}
+
+/// Edge detection over a stream of samples, carrying its state across chunks
+#[derive(Debug)]
+pub struct Trigger {
+    config: TriggerConfig,
+    holdoff_samples: u64,
+    rise_armed: bool,
+    fall_armed: bool,
+    /// Absolute sample index of the last time the trigger fired
+    last: Option<u64>,
+}
+
+impl Trigger {
+    pub fn new(config: TriggerConfig, sample_rate: usize) -> Trigger {
+        Trigger {
+            config,
+            holdoff_samples: (config.holdoff * sample_rate as f64).round() as u64,
+            rise_armed: false,
+            fall_armed: false,
+            last: None,
+        }
+    }
+
+    /// Scan `xs`, the first of which has absolute sample index `start`,
+    /// calling `fire` with the index of each sample the trigger fires on.
+    pub fn process(&mut self, xs: &[f32], start: u64, mut fire: impl FnMut(u64)) {
+        let TriggerConfig {
+            edge,
+            level,
+            hysteresis,
+            ..
+        } = self.config;
+        let rising = edge != Edge::Falling;
+        let falling = edge != Edge::Rising;
+
+        for (i, &x) in xs.iter().enumerate() {
+            let idx = start + i as u64;
+            let mut fired = false;
+            if self.rise_armed && x >= level {
+                self.rise_armed = false;
+                fired |= rising;
+            }
+            if self.fall_armed && x <= level {
+                self.fall_armed = false;
+                fired |= falling;
+            }
+            if x < level - hysteresis {
+                self.rise_armed = true;
+            }
+            if x > level + hysteresis {
+                self.fall_armed = true;
+            }
+
+            let held_off = matches!(self.last, Some(last) if idx < last + self.holdoff_samples);
+            if fired && !held_off {
+                self.last = Some(idx);
+                fire(idx);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fires(config: TriggerConfig, xs: &[f32]) -> Vec<u64> {
+        let mut trigger = Trigger::new(config, 1);
+        let mut fired = Vec::new();
+        // split in two to check state carries across chunks
+        let (a, b) = xs.split_at(xs.len() / 2);
+        trigger.process(a, 0, |i| fired.push(i));
+        trigger.process(b, a.len() as u64, |i| fired.push(i));
+        fired
+    }
+
+    #[test]
+    fn trigger_edges() {
+        let xs = [-1.0, 1.0, -1.0, 1.0, -1.0, 1.0];
+        let mut config = TriggerConfig::default();
+        assert!(fires(config, &xs) == [1, 3, 5]);
+        config.edge = Edge::Falling;
+        assert!(fires(config, &xs) == [2, 4]);
+        config.edge = Edge::Either;
+        assert!(fires(config, &xs) == [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn trigger_hysteresis_holdoff() {
+        // noise around the level only re-fires once it drops below the band
+        let xs = [-1.0, 0.1, -0.05, 0.1, -0.05, 0.1, -1.0, 1.0];
+        let mut config = TriggerConfig {
+            hysteresis: 0.1,
+            ..TriggerConfig::default()
+        };
+        assert!(fires(config, &xs) == [1, 7]);
+        config.hysteresis = 0.0;
+        assert!(fires(config, &xs) == [1, 3, 5, 7]);
+        // with a sample rate of 1 holdoff is in samples
+        config.holdoff = 4.0;
+        assert!(fires(config, &xs) == [1, 5]);
+    }
+}
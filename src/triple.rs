@@ -0,0 +1,113 @@
+use std::cell::UnsafeCell;
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+/// Set on the shared index when it holds a value the reader hasn't taken
+const DIRTY: u8 = 0b100;
+const INDEX: u8 = 0b011;
+
+/// Three copies of a value, one each owned by the writer and reader, and one
+/// in between they swap theirs with. Neither side ever waits on the other:
+/// the writer publishes by swapping its copy into the middle and the reader
+/// picks up the newest published copy the same way.
+struct Shared<T> {
+    bufs: [UnsafeCell<T>; 3],
+    /// Index of the middle copy, plus DIRTY when it was published since the
+    /// reader last swapped
+    middle: AtomicU8,
+}
+
+// Each copy is only ever accessed by whichever of the Input or Output holds
+// its index, and the indices are handed over through `middle`.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+/// Writing end of a triple buffer
+pub struct Input<T> {
+    shared: Arc<Shared<T>>,
+    idx: u8,
+}
+
+/// Reading end of a triple buffer
+pub struct Output<T> {
+    shared: Arc<Shared<T>>,
+    idx: u8,
+}
+
+/// A triple buffer with every copy starting as `init`
+pub fn triple<T: Clone>(init: T) -> (Input<T>, Output<T>) {
+    let shared = Arc::new(Shared {
+        bufs: [
+            UnsafeCell::new(init.clone()),
+            UnsafeCell::new(init.clone()),
+            UnsafeCell::new(init),
+        ],
+        middle: AtomicU8::new(1),
+    });
+    (
+        Input {
+            shared: shared.clone(),
+            idx: 0,
+        },
+        Output { shared, idx: 2 },
+    )
+}
+
+impl<T: Clone> Input<T> {
+    /// Publish a copy of `value`. The copy is made with clone_from into a
+    /// buffer the reader isn't using, so it reuses that buffer's allocations.
+    pub fn publish(&mut self, value: &T) {
+        // SAFETY: idx is ours until we swap it into middle below
+        unsafe { (*self.shared.bufs[self.idx as usize].get()).clone_from(value) };
+        let prev = self.shared.middle.swap(self.idx | DIRTY, Ordering::AcqRel);
+        self.idx = prev & INDEX;
+    }
+}
+
+impl<T> Output<T> {
+    /// The most recently published value
+    pub fn read(&mut self) -> &T {
+        if self.shared.middle.load(Ordering::Relaxed) & DIRTY != 0 {
+            let prev = self.shared.middle.swap(self.idx, Ordering::AcqRel);
+            self.idx = prev & INDEX;
+        }
+        // SAFETY: idx is ours until we next swap it into middle
+        unsafe { &*self.shared.bufs[self.idx as usize].get() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triple_buffer() {
+        let (mut input, mut output) = triple(0);
+        assert!(*output.read() == 0);
+        input.publish(&1);
+        input.publish(&2);
+        // only the newest value is seen, and it stays put until the next publish
+        assert!(*output.read() == 2);
+        assert!(*output.read() == 2);
+        input.publish(&3);
+        assert!(*output.read() == 3);
+
+        // a writer on another thread never leaves a value half written
+        let (mut input, mut output) = triple(vec![0u64; 64]);
+        let writer = std::thread::spawn(move || {
+            for i in 1..10_000 {
+                input.publish(&vec![i; 64]);
+            }
+        });
+        let mut last = 0;
+        while !writer.is_finished() {
+            let v = output.read();
+            assert!(v.iter().all(|x| *x == v[0]));
+            assert!(v[0] >= last);
+            last = v[0];
+        }
+        writer.join().expect("writer to finish");
+        assert!(output.read()[0] == 9_999);
+    }
+}
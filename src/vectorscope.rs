@@ -0,0 +1,237 @@
+use crate::app::{port_color, XPlot};
+use crate::meters::bar_ui;
+use crate::osc::Control;
+use crate::portbuf;
+use egui::plot::{Line, LineStyle, Plot, PlotPoints, Points};
+use std::f64::consts::FRAC_1_SQRT_2;
+
+/// How a stereo pair is mapped onto the plot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Rotated 45 degrees so mid is vertical and side horizontal, left
+    /// leaning up to the left and right up to the right
+    Goniometer,
+    /// Left on x and right on y
+    Lissajous,
+}
+
+impl Mode {
+    const ALL: [Mode; 2] = [Mode::Goniometer, Mode::Lissajous];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Mode::Goniometer => "Goniometer (M/S)",
+            Mode::Lissajous => "Lissajous (L/R)",
+        }
+    }
+
+    fn point(&self, l: f32, r: f32) -> [f64; 2] {
+        let (l, r) = (l as f64, r as f64);
+        match self {
+            Mode::Goniometer => [(r - l) * FRAC_1_SQRT_2, (l + r) * FRAC_1_SQRT_2],
+            Mode::Lissajous => [l, r],
+        }
+    }
+}
+
+/// Phase correlation of a stereo pair in [-1, 1]: 1 when in phase, 0 when
+/// unrelated and -1 when out of phase. 0 for silence.
+fn correlation(l: &[f32], r: &[f32]) -> f32 {
+    let (mut lr, mut ll, mut rr) = (0.0f64, 0.0f64, 0.0f64);
+    for (l, r) in l.iter().zip(r) {
+        let (l, r) = (*l as f64, *r as f64);
+        lr += l * r;
+        ll += l * l;
+        rr += r * r;
+    }
+    match ll * rr > 0.0 {
+        true => (lr / (ll * rr).sqrt()) as f32,
+        false => 0.0,
+    }
+}
+
+/// Stereo image of a pair of ports and their phase correlation, drawn from
+/// the same stretch of samples of each
+pub struct Vectorscope {
+    /// port_idx of the ports used as left and right, the first two enabled
+    /// ports when unset or gone
+    left: Option<usize>,
+    right: Option<usize>,
+    mode: Mode,
+    /// Seconds of the latest samples drawn
+    window: f64,
+    gain: f64,
+    frozen: bool,
+    /// Samples last drawn, as (first sample index, left and right)
+    samples: Option<(u64, Vec<Vec<f32>>)>,
+}
+
+impl Vectorscope {
+    pub fn new() -> Self {
+        Vectorscope {
+            left: None,
+            right: None,
+            mode: Mode::Goniometer,
+            window: 0.05,
+            gain: 1.0,
+            frozen: false,
+            samples: None,
+        }
+    }
+
+    fn controls_ui(&mut self, ui: &mut egui::Ui, portbufs: &[portbuf::PortBuf]) {
+        let name = |idx: Option<usize>| {
+            idx.and_then(|idx| portbufs.iter().find(|pb| pb.port_idx == idx))
+                .map_or("-", |pb| pb.name.as_str())
+        };
+        ui.horizontal(|ui| {
+            for (label, port) in [("Left", &mut self.left), ("Right", &mut self.right)] {
+                egui::ComboBox::from_label(label)
+                    .selected_text(name(*port))
+                    .show_ui(ui, |ui| {
+                        for pb in portbufs {
+                            ui.selectable_value(port, Some(pb.port_idx), &pb.name);
+                        }
+                    });
+            }
+            egui::ComboBox::from_label("Mode")
+                .selected_text(self.mode.label())
+                .show_ui(ui, |ui| {
+                    for mode in Mode::ALL {
+                        ui.selectable_value(&mut self.mode, mode, mode.label());
+                    }
+                });
+            ui.add(
+                egui::Slider::new(&mut self.window, 0.005..=0.5)
+                    .logarithmic(true)
+                    .suffix(" s")
+                    .text("Window"),
+            );
+            ui.add(
+                egui::Slider::new(&mut self.gain, 0.1..=100.0)
+                    .logarithmic(true)
+                    .text("Gain"),
+            );
+        });
+    }
+
+    /// The selected pair, falling back to the first two enabled ports
+    fn pair<'a>(
+        &mut self,
+        portbufs: &'a [portbuf::PortBuf],
+    ) -> Option<(&'a portbuf::PortBuf, &'a portbuf::PortBuf)> {
+        let find =
+            |idx: Option<usize>| idx.and_then(|idx| portbufs.iter().find(|pb| pb.port_idx == idx));
+        let mut enabled = portbufs.iter().filter(|pb| pb.enabled);
+        let left = find(self.left).or_else(|| enabled.next())?;
+        let right = find(self.right).or_else(|| enabled.find(|pb| pb.port_idx != left.port_idx))?;
+        self.left = Some(left.port_idx);
+        self.right = Some(right.port_idx);
+        Some((left, right))
+    }
+}
+
+impl XPlot for Vectorscope {
+    fn name(&self) -> &'static str {
+        "Vectorscope"
+    }
+
+    fn control(&mut self, control: &Control, _bufs: &[portbuf::PortBuf]) -> bool {
+        match control {
+            Control::Freeze(frozen) => self.frozen = *frozen,
+            _ => return false,
+        }
+        true
+    }
+
+    fn plot(&mut self, ui: &mut egui::Ui, portbufs: &[portbuf::PortBuf]) {
+        self.controls_ui(ui, portbufs);
+        let (left, right) = match self.pair(portbufs) {
+            Some(pair) => pair,
+            None => {
+                ui.label("Needs two ports");
+                return;
+            }
+        };
+
+        if !self.frozen || self.samples.is_none() {
+            let n = left.samples_in(self.window);
+            self.samples = portbuf::aligned_samples(&[left, right], n);
+        }
+        let (points, r) = match &self.samples {
+            Some((_, samples)) => {
+                let gain = self.gain as f32;
+                let points: Vec<[f64; 2]> = samples[0]
+                    .iter()
+                    .zip(&samples[1])
+                    .map(|(l, r)| self.mode.point(l * gain, r * gain))
+                    .collect();
+                (points, correlation(&samples[0], &samples[1]))
+            }
+            None => (Vec::new(), 0.0),
+        };
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Correlation {r:+.2}"));
+            let color = match r < 0.0 {
+                true => egui::Color32::RED,
+                false => egui::Color32::GREEN,
+            };
+            let width = ui.available_width().max(1.0);
+            bar_ui(ui, width, (-1.0, 1.0), 0.0, &[], &[(r, color)]);
+        });
+
+        let mode = self.mode;
+        Plot::new("Vectorscope")
+            .data_aspect(1.0)
+            .include_x(-1.0)
+            .include_x(1.0)
+            .include_y(-1.0)
+            .include_y(1.0)
+            .show_axes([false, false])
+            .label_formatter(move |_, p| match mode {
+                Mode::Goniometer => format!("S {:.3}\nM {:.3}", p.x, p.y),
+                Mode::Lissajous => format!("L {:.3}\nR {:.3}", p.x, p.y),
+            })
+            .show(ui, |plot_ui| {
+                // axes of the two channels, and of their mid and side
+                let guide = |name: &str, l: f32, r: f32| {
+                    Line::new(PlotPoints::new(vec![mode.point(-l, -r), mode.point(l, r)]))
+                        .color(egui::Color32::GRAY)
+                        .style(LineStyle::dashed_loose())
+                        .name(name)
+                };
+                plot_ui.line(guide("L", 1.0, 0.0));
+                plot_ui.line(guide("R", 0.0, 1.0));
+                plot_ui.line(guide("M", 0.5, 0.5));
+                plot_ui.line(guide("S", 0.5, -0.5));
+                plot_ui.points(
+                    Points::new(PlotPoints::new(points))
+                        .radius(1.0)
+                        .color(port_color(left.port_idx)),
+                );
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stereo_image() {
+        let xs = [0.5, -1.0, 0.25];
+        let inverted: Vec<f32> = xs.iter().map(|x| -x).collect();
+        assert!((correlation(&xs, &xs) - 1.0).abs() < 1e-6);
+        assert!((correlation(&xs, &inverted) + 1.0).abs() < 1e-6);
+        assert!(correlation(&xs, &[0.0; 3]) == 0.0);
+
+        // mono is vertical, and a channel on its own leans its own way
+        let [x, y] = Mode::Goniometer.point(0.5, 0.5);
+        assert!(x == 0.0 && y > 0.0);
+        let [x, y] = Mode::Goniometer.point(1.0, 0.0);
+        assert!(x < 0.0 && (x + y).abs() < 1e-9);
+        let [x, y] = Mode::Goniometer.point(0.0, 1.0);
+        assert!(x > 0.0 && (x - y).abs() < 1e-9);
+    }
+}
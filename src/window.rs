@@ -0,0 +1,149 @@
+use std::f64::consts::TAU;
+
+/// Betas offered for the Kaiser window. Much past this its coefficients
+/// overflow.
+pub const KAISER_BETA: std::ops::RangeInclusive<f32> = 0.0..=20.0;
+
+/// Window functions applied to each FFT frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    BlackmanHarris,
+    FlatTop,
+    Kaiser { beta: f32 },
+}
+
+impl Window {
+    pub const ALL: [Window; 6] = [
+        Window::Rectangular,
+        Window::Hann,
+        Window::Hamming,
+        Window::BlackmanHarris,
+        Window::FlatTop,
+        Window::Kaiser { beta: 8.6 },
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Window::Rectangular => "Rectangular",
+            Window::Hann => "Hann",
+            Window::Hamming => "Hamming",
+            Window::BlackmanHarris => "Blackman-Harris",
+            Window::FlatTop => "Flat-top",
+            Window::Kaiser { .. } => "Kaiser",
+        }
+    }
+
+    /// Periodic (DFT-even) window coefficients of length n
+    pub fn coefficients(&self, n: usize) -> Vec<f32> {
+        let cosine_sum = |a: &[f64]| -> Vec<f32> {
+            (0..n)
+                .map(|i| {
+                    let x = TAU * i as f64 / n as f64;
+                    a.iter()
+                        .enumerate()
+                        .map(|(k, ak)| if k % 2 == 0 { 1.0 } else { -1.0 } * ak * (k as f64 * x).cos())
+                        .sum::<f64>() as f32
+                })
+                .collect()
+        };
+
+        match self {
+            Window::Rectangular => vec![1.0; n],
+            Window::Hann => cosine_sum(&[0.5, 0.5]),
+            Window::Hamming => cosine_sum(&[0.54, 0.46]),
+            Window::BlackmanHarris => cosine_sum(&[0.35875, 0.48829, 0.14128, 0.01168]),
+            // HFT coefficients as used by matlab / scipy flattop
+            Window::FlatTop => cosine_sum(&[
+                0.215_578_95,
+                0.416_631_58,
+                0.277_263_158,
+                0.083_578_947,
+                0.006_947_368,
+            ]),
+            Window::Kaiser { beta } => {
+                let beta = *beta as f64;
+                let norm = bessel_i0(beta);
+                (0..n)
+                    .map(|i| {
+                        let r = 2.0 * i as f64 / n as f64 - 1.0;
+                        (bessel_i0(beta * (1.0 - r * r).max(0.0).sqrt()) / norm) as f32
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Corrections needed to read calibrated values off a windowed spectrum
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowGains {
+    /// Sum of the coefficients. Dividing a bin by this reads a sinusoid's
+    /// amplitude rather than the attenuated coherent sum.
+    pub sum: f32,
+    /// Mean of the coefficients
+    pub coherent_gain: f32,
+    /// Equivalent noise bandwidth in bins. Dividing coherent gain corrected
+    /// power by this (and the bin width) gives a noise power density.
+    pub enbw: f32,
+}
+
+impl WindowGains {
+    pub fn new(coeffs: &[f32]) -> WindowGains {
+        let sum: f64 = coeffs.iter().map(|&w| w as f64).sum();
+        let sum_sqr: f64 = coeffs.iter().map(|&w| (w as f64).powi(2)).sum();
+        let n = coeffs.len() as f64;
+        WindowGains {
+            sum: sum as f32,
+            coherent_gain: (sum / n) as f32,
+            enbw: (n * sum_sqr / (sum * sum)) as f32,
+        }
+    }
+}
+
+/// Zeroth order modified Bessel function of the first kind
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+    for k in 1..50 {
+        term *= (half_x / k as f64).powi(2);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_gains() {
+        let n = 4096;
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-3;
+
+        let gains = WindowGains::new(&Window::Rectangular.coefficients(n));
+        assert!(close(gains.coherent_gain, 1.0) && close(gains.enbw, 1.0));
+
+        let gains = WindowGains::new(&Window::Hann.coefficients(n));
+        assert!(close(gains.coherent_gain, 0.5) && close(gains.enbw, 1.5));
+
+        let gains = WindowGains::new(&Window::Hamming.coefficients(n));
+        assert!(close(gains.coherent_gain, 0.54) && close(gains.enbw, 1.363));
+
+        let gains = WindowGains::new(&Window::BlackmanHarris.coefficients(n));
+        assert!(close(gains.coherent_gain, 0.35875) && close(gains.enbw, 2.004));
+
+        let gains = WindowGains::new(&Window::FlatTop.coefficients(n));
+        assert!(close(gains.coherent_gain, 0.2156) && close(gains.enbw, 3.770));
+
+        // a beta of 0 is a rectangular window
+        let gains = WindowGains::new(&Window::Kaiser { beta: 0.0 }.coefficients(n));
+        assert!(close(gains.coherent_gain, 1.0) && close(gains.enbw, 1.0));
+    }
+}